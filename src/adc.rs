@@ -1,4 +1,8 @@
 use core::marker::PhantomData;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+use core::ops::Deref;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+use core::time::Duration;
 
 #[cfg(not(feature = "riscv-ulp-hal"))]
 use esp_idf_sys::*;
@@ -6,8 +10,26 @@ use esp_idf_sys::*;
 #[cfg(feature = "riscv-ulp-hal")]
 use crate::riscv_ulp_hal::sys::*;
 
+#[cfg(not(feature = "riscv-ulp-hal"))]
+use crate::delay::TickType;
 #[cfg(all(esp32, not(feature = "riscv-ulp-hal")))]
 use crate::hall;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+use crate::units::MilliVolts;
+
+/// Longest pause (in ticks) between two attempts of [`PoweredAdc::read_blocking`]
+#[cfg(not(feature = "riscv-ulp-hal"))]
+const MAX_BACKOFF_TICKS: TickType_t = 16;
+
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub mod continuous;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+mod filter;
+#[cfg(all(any(esp32s2, esp32s3, esp32c3), not(feature = "riscv-ulp-hal")))]
+pub mod monitor;
+
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub use filter::*;
 
 pub trait Adc: Send {
     fn unit() -> adc_unit_t;
@@ -147,14 +169,19 @@ unsafe impl<ADC: Adc> Send for PoweredAdc<ADC> {}
 
 #[cfg(not(feature = "riscv-ulp-hal"))]
 impl<ADC: Adc> PoweredAdc<ADC> {
+    /// The eFuse calibration data used to convert raw readings to millivolts
     #[cfg(all(esp32, esp_idf_comp_esp_adc_cal_enabled))]
-    const CALIBRATION_SCHEME: esp_adc_cal_value_t = esp_adc_cal_value_t_ESP_ADC_CAL_VAL_EFUSE_VREF;
+    pub const CALIBRATION_SCHEME: esp_adc_cal_value_t =
+        esp_adc_cal_value_t_ESP_ADC_CAL_VAL_EFUSE_VREF;
 
+    /// The eFuse calibration data used to convert raw readings to millivolts
     #[cfg(all(any(esp32c3, esp32s2), esp_idf_comp_esp_adc_cal_enabled))]
-    const CALIBRATION_SCHEME: esp_adc_cal_value_t = esp_adc_cal_value_t_ESP_ADC_CAL_VAL_EFUSE_TP;
+    pub const CALIBRATION_SCHEME: esp_adc_cal_value_t =
+        esp_adc_cal_value_t_ESP_ADC_CAL_VAL_EFUSE_TP;
 
+    /// The eFuse calibration data used to convert raw readings to millivolts
     #[cfg(all(esp32s3, esp_idf_comp_esp_adc_cal_enabled))]
-    const CALIBRATION_SCHEME: esp_adc_cal_value_t =
+    pub const CALIBRATION_SCHEME: esp_adc_cal_value_t =
         esp_adc_cal_value_t_ESP_ADC_CAL_VAL_EFUSE_TP_FIT;
 
     #[cfg(not(esp32s2))]
@@ -189,6 +216,70 @@ impl<ADC: Adc> PoweredAdc<ADC> {
         self.adc
     }
 
+    /// Read the raw conversion result of `pin`, without converting it to millivolts
+    pub fn read_raw<AN, PIN>(&mut self, _pin: &mut PIN) -> nb::Result<u16, EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        let measurement = self.read_raw_internal(ADC::unit(), PIN::channel() as adc_channel_t)?;
+
+        Ok(measurement as u16)
+    }
+
+    /// Read the voltage of `pin`, calibrated if calibration is enabled in the configuration
+    pub fn read_voltage<AN, PIN>(&mut self, _pin: &mut PIN) -> nb::Result<MilliVolts, EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        let mv = self.read(
+            ADC::unit(),
+            PIN::channel() as adc_channel_t,
+            AN::attenuation(),
+        )?;
+
+        Ok(MilliVolts(mv as _))
+    }
+
+    /// Read the voltage of `pin` in millivolts, waiting for up to `timeout` (`None` waits forever)
+    /// while the ADC unit is in use by Wi-Fi
+    ///
+    /// Only ADC2 is shared with Wi-Fi; the pause between attempts starts at one tick and
+    /// doubles up to 16 ticks. Fails with `ESP_ERR_TIMEOUT` if the unit did not become available.
+    pub fn read_blocking<AN, PIN>(
+        &mut self,
+        pin: &mut PIN,
+        timeout: Option<Duration>,
+    ) -> Result<MilliVolts, EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        read_with_backoff(timeout, || self.read_voltage(pin))
+    }
+
+    /// Returns true if raw readings are converted to millivolts using the eFuse calibration data
+    pub fn is_calibrated(&self) -> bool {
+        #[cfg(esp_idf_comp_esp_adc_cal_enabled)]
+        let calibrated = self.cal_characteristics.is_some();
+
+        #[cfg(not(esp_idf_comp_esp_adc_cal_enabled))]
+        let calibrated = false;
+
+        calibrated
+    }
+
+    /// The calibration characteristics used to convert raw readings taken with `attenuation`
+    /// to millivolts, or `None` if calibration is not enabled
+    #[cfg(esp_idf_comp_esp_adc_cal_enabled)]
+    pub fn calibration_characteristics(
+        &mut self,
+        attenuation: adc_atten_t,
+    ) -> Result<Option<esp_adc_cal_characteristics_t>, EspError> {
+        self.get_cal_characteristics(attenuation)
+    }
+
     fn raw_to_voltage(
         &mut self,
         measurement: c_types::c_int,
@@ -270,12 +361,48 @@ impl<ADC: Adc> PoweredAdc<ADC> {
         }
     }
 
-    fn read(
+    /// Take `samples` readings of `pin` in a row, combine them with `pipeline`
+    /// (a [`Pipeline`] or a plain [`Filter`]) and return the result in millivolts
+    ///
+    /// `samples` must be between 1 and [`MAX_AVERAGED_SAMPLES`]. On ADC2, the whole burst
+    /// is abandoned with `WouldBlock` if Wi-Fi takes over the unit in the middle of it.
+    pub fn read_averaged<AN, PIN>(
+        &mut self,
+        _pin: &mut PIN,
+        samples: usize,
+        pipeline: impl Into<Pipeline>,
+    ) -> nb::Result<MilliVolts, EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        if !(1..=MAX_AVERAGED_SAMPLES).contains(&samples) {
+            return Err(nb::Error::Other(
+                EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap(),
+            ));
+        }
+
+        let mut buf = [0_u16; MAX_AVERAGED_SAMPLES];
+
+        for sample in &mut buf[..samples] {
+            *sample = self.read_raw_internal(ADC::unit(), PIN::channel() as adc_channel_t)? as u16;
+        }
+
+        let measurement = pipeline
+            .into()
+            .apply(&mut buf[..samples])
+            .ok_or_else(|| nb::Error::Other(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap()))?;
+
+        let mv = self.raw_to_voltage(measurement as _, AN::attenuation())?;
+
+        Ok(MilliVolts(mv as _))
+    }
+
+    fn read_raw_internal(
         &mut self,
         unit: adc_unit_t,
         channel: adc_channel_t,
-        atten: adc_atten_t,
-    ) -> nb::Result<u16, EspError> {
+    ) -> nb::Result<c_types::c_int, EspError> {
         let mut measurement = 0_i32;
 
         if unit == adc_unit_t_ADC_UNIT_1 {
@@ -292,15 +419,179 @@ impl<ADC: Adc> PoweredAdc<ADC> {
             }
         };
 
+        Ok(measurement)
+    }
+
+    fn read(
+        &mut self,
+        unit: adc_unit_t,
+        channel: adc_channel_t,
+        atten: adc_atten_t,
+    ) -> nb::Result<u16, EspError> {
+        let measurement = self.read_raw_internal(unit, channel)?;
+
         Ok(self.raw_to_voltage(measurement, atten)?)
     }
 
     #[cfg(esp32)]
     fn read_hall(&mut self) -> nb::Result<u16, EspError> {
-        let measurement = unsafe { hall_sensor_read() };
+        let measurement = self.read_hall_raw()?;
 
         Ok(self.raw_to_voltage(measurement, adc_atten_t_ADC_ATTEN_DB_0)?)
     }
+
+    #[cfg(esp32)]
+    pub(crate) fn read_hall_raw(&mut self) -> Result<c_types::c_int, EspError> {
+        Ok(unsafe { hall_sensor_read() })
+    }
+}
+
+/// Retry `read` while it would block, pausing for an exponentially growing number of ticks
+#[cfg(not(feature = "riscv-ulp-hal"))]
+fn read_with_backoff<T>(
+    timeout: Option<Duration>,
+    mut read: impl FnMut() -> nb::Result<T, EspError>,
+) -> Result<T, EspError> {
+    let mut remaining = timeout.map(|timeout| TickType::from(timeout).0);
+    let mut backoff: TickType_t = 1;
+
+    loop {
+        match read() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::Other(e)) => return Err(e),
+            Err(nb::Error::WouldBlock) => (),
+        }
+
+        let delay = match remaining {
+            Some(0) => return Err(EspError::from(ESP_ERR_TIMEOUT as i32).unwrap()),
+            Some(ref mut ticks) => {
+                let delay = core::cmp::min(backoff, *ticks);
+                *ticks -= delay;
+
+                delay
+            }
+            None => backoff,
+        };
+
+        unsafe { vTaskDelay(delay) };
+
+        backoff = core::cmp::min(backoff * 2, MAX_BACKOFF_TICKS);
+    }
+}
+
+/// A [`PoweredAdc`] which can be shared between threads, each reading its own channels
+/// through an [`AdcChannel`] handle
+///
+/// The handles can borrow the shared ADC, or hold it in an `Arc`:
+///
+/// ```
+/// let shared = Arc::new(SharedAdc::new(powered_adc2));
+///
+/// let mut battery = AdcChannel::new(shared.clone(), pins.gpio13.into_analog_atten_11db()?);
+/// let mut light = AdcChannel::new(shared, pins.gpio14.into_analog_atten_11db()?);
+///
+/// std::thread::spawn(move || loop {
+///     info!("Battery: {}", battery.read_blocking(None).unwrap());
+/// });
+/// ```
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub struct SharedAdc<ADC: Adc>(crate::mutex::Mutex<PoweredAdc<ADC>>);
+
+#[cfg(not(feature = "riscv-ulp-hal"))]
+impl<ADC: Adc> SharedAdc<ADC> {
+    pub fn new(adc: PoweredAdc<ADC>) -> Self {
+        Self(crate::mutex::Mutex::new(adc))
+    }
+
+    /// Create a handle reading the channel of `pin`
+    pub fn channel<AN, PIN>(&self, pin: PIN) -> AdcChannel<&Self, ADC, AN, PIN>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        AdcChannel::new(self, pin)
+    }
+
+    pub fn release(self) -> PoweredAdc<ADC> {
+        self.0.into_inner()
+    }
+
+    fn with_adc<R>(&self, f: impl FnOnce(&mut PoweredAdc<ADC>) -> R) -> R {
+        f(&mut self.0.lock())
+    }
+}
+
+/// Handle reading one channel of a [`SharedAdc`]
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub struct AdcChannel<S, ADC, AN, PIN>
+where
+    S: Deref<Target = SharedAdc<ADC>>,
+    ADC: Adc,
+    AN: Analog<ADC>,
+    PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+{
+    adc: S,
+    pin: PIN,
+    _atten: PhantomData<AN>,
+}
+
+#[cfg(not(feature = "riscv-ulp-hal"))]
+impl<S, ADC, AN, PIN> AdcChannel<S, ADC, AN, PIN>
+where
+    S: Deref<Target = SharedAdc<ADC>>,
+    ADC: Adc,
+    AN: Analog<ADC>,
+    PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+{
+    pub fn new(adc: S, pin: PIN) -> Self {
+        Self {
+            adc,
+            pin,
+            _atten: PhantomData,
+        }
+    }
+
+    /// Read the voltage of the channel in millivolts
+    pub fn read(&mut self) -> nb::Result<MilliVolts, EspError> {
+        let pin = &mut self.pin;
+
+        self.adc.with_adc(|adc| adc.read_voltage(pin))
+    }
+
+    /// Read the raw conversion result of the channel
+    pub fn read_raw(&mut self) -> nb::Result<u16, EspError> {
+        let pin = &mut self.pin;
+
+        self.adc.with_adc(|adc| adc.read_raw(pin))
+    }
+
+    /// Like [`PoweredAdc::read_blocking`]; the shared ADC is not locked between attempts
+    pub fn read_blocking(&mut self, timeout: Option<Duration>) -> Result<MilliVolts, EspError> {
+        read_with_backoff(timeout, || self.read())
+    }
+
+    pub fn release(self) -> (S, PIN) {
+        (self.adc, self.pin)
+    }
+}
+
+#[cfg(not(feature = "riscv-ulp-hal"))]
+impl<S, ADC, AN, PIN> embedded_hal_0_2::adc::OneShot<AN, u16, PIN> for AdcChannel<S, ADC, AN, PIN>
+where
+    S: Deref<Target = SharedAdc<ADC>>,
+    ADC: Adc,
+    AN: Analog<ADC>,
+    PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+{
+    type Error = EspError;
+
+    /// Reads the channel of the handle; `pin` is only used to select the trait implementation
+    fn read(&mut self, _pin: &mut PIN) -> nb::Result<u16, Self::Error> {
+        let pin = &mut self.pin;
+
+        self.adc
+            .with_adc(|adc| embedded_hal_0_2::adc::OneShot::<AN, u16, PIN>::read(adc, pin))
+    }
 }
 
 #[cfg(not(feature = "riscv-ulp-hal"))]
@@ -321,6 +612,8 @@ where
     }
 }
 
+/// Converts the Hall sensor output as if it was a voltage at 0 dB attenuation;
+/// [`hall::HallSensor::read_raw`] returns the signed sensor output instead
 #[cfg(all(esp32, not(feature = "riscv-ulp-hal")))]
 impl embedded_hal_0_2::adc::OneShot<ADC1, u16, hall::HallSensor> for PoweredAdc<ADC1> {
     type Error = EspError;
@@ -0,0 +1,620 @@
+//! ADC continuous (DMA) mode driver.
+//!
+//! The digital controller samples a pattern of channels of one ADC unit at a fixed rate,
+//! and stores the results in a driver buffer through DMA, from where they are read in frames.
+//!
+//! # Example
+//!
+//! ```
+//! use esp_idf_hal::adc::continuous;
+//! use esp_idf_hal::prelude::*;
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut pin = peripherals.pins.gpio34.into_analog_atten_11db()?;
+//!
+//! let channels = continuous::Channels::new().channel(&pin);
+//! let config = continuous::config::Config::new().sample_freq(Hertz(20_000));
+//!
+//! let mut adc = continuous::ContinuousAdc::new(peripherals.adc1, &channels, &config)?;
+//! adc.start()?;
+//!
+//! let mut samples = [continuous::AdcMeasurement::new(); 64];
+//! let count = adc.read(&mut samples, None)?;
+//!
+//! for sample in &samples[..count] {
+//!     info!("channel {}: {}", sample.channel(), sample.data());
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use esp_idf_sys::*;
+
+use crate::interrupt::asynch::HalIsrNotification;
+use crate::interrupt::task::{Pump, TaskConfig};
+
+use super::{Adc, Analog};
+
+/// How long the async task blocks reading a frame, and so how long stopping it may take
+const ASYNC_TASK_POLL_MS: u32 = 10;
+const ASYNC_BUFFER_SIZE: usize = 256;
+
+/// Number of conversions after which the ESP32 and ESP32-S2 digital controllers
+/// stop and restart the pattern (1..=255)
+///
+/// These controllers require the conversion limit in single unit mode; 250 is the
+/// value the ESP-IDF continuous read example uses. Other chips ignore it.
+const CONV_LIMIT_NUM: u32 = 250;
+
+/// Set while a [`ContinuousAdc`] exists, as ESP-IDF has a single digital controller driver
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Maximum number of entries in the pattern table of the digital controller
+pub const MAX_CHANNELS: usize = SOC_ADC_PATT_LEN_MAX as usize;
+
+pub mod config {
+    use crate::interrupt::task::TaskConfig;
+    use crate::units::*;
+
+    #[derive(Debug, Copy, Clone)]
+    pub struct Config {
+        /// Conversions per second, across all channels of the pattern
+        pub sample_freq: Hertz,
+        /// Number of bytes the DMA transfers at once; reads complete in multiples of it
+        pub frame_size: usize,
+        /// Size in bytes of the driver buffer holding frames which have not been read yet
+        pub buffer_size: usize,
+        /// The task which reads the frames from the driver for async readers,
+        /// as the driver offers no interrupt hook
+        pub async_task: TaskConfig,
+    }
+
+    impl Config {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn sample_freq(mut self, sample_freq: Hertz) -> Self {
+            self.sample_freq = sample_freq;
+            self
+        }
+
+        #[must_use]
+        pub fn frame_size(mut self, frame_size: usize) -> Self {
+            self.frame_size = frame_size;
+            self
+        }
+
+        #[must_use]
+        pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+            self.buffer_size = buffer_size;
+            self
+        }
+
+        #[must_use]
+        pub fn async_task(mut self, async_task: TaskConfig) -> Self {
+            self.async_task = async_task;
+            self
+        }
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                sample_freq: Hertz(20_000),
+                frame_size: 256,
+                buffer_size: 1024,
+                async_task: TaskConfig::new().name("adc_async").stack_size(2048),
+            }
+        }
+    }
+}
+
+/// The channels sampled by the digital controller, in pattern table order
+pub struct Channels<ADC: Adc> {
+    patterns: [adc_digi_pattern_config_t; MAX_CHANNELS],
+    len: usize,
+    _adc: PhantomData<ADC>,
+}
+
+impl<ADC: Adc> Channels<ADC> {
+    pub fn new() -> Self {
+        Self {
+            patterns: [Default::default(); MAX_CHANNELS],
+            len: 0,
+            _adc: PhantomData,
+        }
+    }
+
+    /// Append the channel of `pin`, sampled with the attenuation of the pin's analog mode
+    ///
+    /// # Panics
+    ///
+    /// If the pattern table already holds [`MAX_CHANNELS`] entries.
+    #[must_use]
+    pub fn channel<AN, PIN>(mut self, _pin: &PIN) -> Self
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        assert!(self.len < MAX_CHANNELS, "ADC pattern table is full");
+
+        self.patterns[self.len] = adc_digi_pattern_config_t {
+            atten: AN::attenuation() as _,
+            channel: PIN::channel(),
+            unit: unit_index(ADC::unit()),
+            bit_width: SOC_ADC_DIGI_MAX_BITWIDTH as _,
+        };
+
+        self.len += 1;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<ADC: Adc> Default for Channels<ADC> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One conversion result of the digital controller
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct AdcMeasurement(adc_digi_output_data_t);
+
+impl AdcMeasurement {
+    pub fn new() -> Self {
+        Self(Default::default())
+    }
+
+    /// The raw conversion result
+    pub fn data(&self) -> u16 {
+        #[cfg(esp32)]
+        let data = unsafe { self.0.__bindgen_anon_1.type1.data() };
+
+        #[cfg(not(esp32))]
+        let data = unsafe { self.0.__bindgen_anon_1.type2.data() };
+
+        data as _
+    }
+
+    /// The ADC channel which was converted
+    pub fn channel(&self) -> u8 {
+        #[cfg(esp32)]
+        let channel = unsafe { self.0.__bindgen_anon_1.type1.channel() };
+
+        #[cfg(not(esp32))]
+        let channel = unsafe { self.0.__bindgen_anon_1.type2.channel() };
+
+        channel as _
+    }
+
+    /// The ADC unit which did the conversion
+    pub fn unit(&self) -> adc_unit_t {
+        #[cfg(esp32)]
+        let unit = 0;
+
+        #[cfg(not(esp32))]
+        let unit = unsafe { self.0.__bindgen_anon_1.type2.unit() };
+
+        if unit == 0 {
+            adc_unit_t_ADC_UNIT_1
+        } else {
+            adc_unit_t_ADC_UNIT_2
+        }
+    }
+}
+
+impl Default for AdcMeasurement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for AdcMeasurement {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AdcMeasurement")
+            .field("unit", &self.unit())
+            .field("channel", &self.channel())
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+/// ADC continuous mode driver
+///
+/// Only one instance may exist at a time, whichever unit it samples, as the digital
+/// controller and its ESP-IDF driver are shared by both units.
+pub struct ContinuousAdc<ADC: Adc> {
+    adc: ADC,
+    frame_size: usize,
+    async_task: TaskConfig,
+    started: bool,
+}
+
+impl<ADC: Adc> ContinuousAdc<ADC> {
+    pub fn new(
+        adc: ADC,
+        channels: &Channels<ADC>,
+        config: &config::Config,
+    ) -> Result<Self, EspError> {
+        if channels.is_empty() {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        if TAKEN.swap(true, Ordering::SeqCst) {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        let result = Self::init(channels, config);
+
+        if result.is_err() {
+            TAKEN.store(false, Ordering::SeqCst);
+        }
+
+        result.map(|_| Self {
+            adc,
+            frame_size: config.frame_size,
+            async_task: config.async_task,
+            started: false,
+        })
+    }
+
+    fn init(channels: &Channels<ADC>, config: &config::Config) -> Result<(), EspError> {
+        let channel_mask = channels.patterns[..channels.len]
+            .iter()
+            .fold(0_u32, |mask, pattern| mask | 1 << pattern.channel);
+
+        let unit1 = ADC::unit() == adc_unit_t_ADC_UNIT_1;
+
+        let init_config = adc_digi_init_config_t {
+            max_store_buf_size: config.buffer_size as _,
+            conv_num_each_intr: config.frame_size as _,
+            adc1_chan_mask: if unit1 { channel_mask } else { 0 },
+            adc2_chan_mask: if unit1 { 0 } else { channel_mask },
+        };
+
+        esp!(unsafe { adc_digi_initialize(&init_config) })?;
+
+        // The driver copies the pattern table
+        let mut patterns = channels.patterns;
+
+        let digi_config = adc_digi_configuration_t {
+            conv_limit_en: cfg!(any(esp32, esp32s2)),
+            conv_limit_num: CONV_LIMIT_NUM,
+            pattern_num: channels.len as _,
+            adc_pattern: patterns.as_mut_ptr(),
+            sample_freq_hz: config.sample_freq.0,
+            conv_mode: if unit1 {
+                adc_digi_convert_mode_t_ADC_CONV_SINGLE_UNIT_1
+            } else {
+                adc_digi_convert_mode_t_ADC_CONV_SINGLE_UNIT_2
+            },
+            #[cfg(esp32)]
+            format: adc_digi_output_format_t_ADC_DIGI_OUTPUT_FORMAT_TYPE1,
+            #[cfg(not(esp32))]
+            format: adc_digi_output_format_t_ADC_DIGI_OUTPUT_FORMAT_TYPE2,
+        };
+
+        if let Err(e) = esp!(unsafe { adc_digi_controller_configure(&digi_config) }) {
+            unsafe { adc_digi_deinitialize() };
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Start sampling
+    pub fn start(&mut self) -> Result<(), EspError> {
+        esp!(unsafe { adc_digi_start() })?;
+
+        self.started = true;
+
+        Ok(())
+    }
+
+    /// Stop sampling; measurements which have not been read yet are kept
+    pub fn stop(&mut self) -> Result<(), EspError> {
+        AsyncState::stop();
+
+        esp!(unsafe { adc_digi_stop() })?;
+
+        self.started = false;
+
+        Ok(())
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Configure hardware IIR filter `filter` to smooth the measurements of the channel of `pin`,
+    /// or disable it if `coefficient` is `None`
+    ///
+    /// The filters are applied by the digital controller, so the measurements
+    /// delivered by [`ContinuousAdc::read`] are already filtered.
+    #[cfg(any(esp32s2, esp32s3))]
+    pub fn set_iir_filter<AN, PIN>(
+        &mut self,
+        filter: IirFilter,
+        _pin: &PIN,
+        coefficient: Option<IirCoefficient>,
+    ) -> Result<(), EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        let index = filter.into();
+
+        if let Some(coefficient) = coefficient {
+            let mut config = adc_digi_filter_t {
+                adc_unit: ADC::unit(),
+                channel: PIN::channel() as _,
+                mode: coefficient.into(),
+            };
+
+            esp!(unsafe { adc_digi_filter_set_config(index, &mut config) })?;
+            esp!(unsafe { adc_digi_filter_enable(index, true) })
+        } else {
+            esp!(unsafe { adc_digi_filter_enable(index, false) })?;
+            esp!(unsafe { adc_digi_filter_reset(index) })
+        }
+    }
+
+    /// Read measurements into `buf`, waiting for up to `timeout` (`None` waits forever)
+    /// for a frame to be available
+    ///
+    /// Returns the number of measurements read, which is 0 on timeout.
+    /// Fails with `ESP_ERR_INVALID_STATE` once [`ContinuousAdc::read_async`] has been used
+    /// since the last [`ContinuousAdc::start`].
+    pub fn read(
+        &mut self,
+        buf: &mut [AdcMeasurement],
+        timeout: Option<Duration>,
+    ) -> Result<usize, EspError> {
+        if ASYNC_STATE.pump.is_running() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        let timeout_ms = timeout.map_or(u32::MAX, |timeout| timeout.as_millis() as u32);
+
+        let len = read_bytes(
+            buf.as_mut_ptr() as *mut u8,
+            mem::size_of_val(buf),
+            timeout_ms,
+        )?;
+
+        Ok(len / mem::size_of::<AdcMeasurement>())
+    }
+
+    /// Read measurements into `buf`, waiting asynchronously for a frame to be available
+    ///
+    /// Returns the number of measurements read. The first call starts the async task
+    /// (see [`config::Config::async_task`]), which takes over reading the frames from
+    /// the driver until [`ContinuousAdc::stop`].
+    ///
+    /// The driver of ESP-IDF 4.4 has no conversion-done callback, so this is not driven
+    /// by the interrupt: the task polls `adc_digi_read_bytes` with a 10ms timeout, and
+    /// hands at most 256 bytes at a time to the readers through a single static buffer.
+    /// Until the readers have consumed that buffer, new measurements pile up in the driver
+    /// buffer (see [`config::Config::buffer_size`]), and are dropped once it is full. Sampling
+    /// faster than 256 bytes per 10ms therefore drops measurements unless the readers keep up.
+    pub async fn read_async(&mut self, buf: &mut [AdcMeasurement]) -> Result<usize, EspError> {
+        if !self.started {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        AsyncState::start(self.frame_size, &self.async_task)?;
+
+        let state = &ASYNC_STATE;
+
+        loop {
+            let available = state.available.load(Ordering::SeqCst);
+
+            if available > 0 {
+                let offset = state.offset.load(Ordering::SeqCst);
+                let len = core::cmp::min(
+                    available - offset,
+                    mem::size_of_val(buf) / mem::size_of::<AdcMeasurement>()
+                        * mem::size_of::<AdcMeasurement>(),
+                );
+
+                // The async task does not touch the buffer until all of it has been consumed
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        (state.buffer.get() as *const u8).add(offset),
+                        buf.as_mut_ptr() as *mut u8,
+                        len,
+                    )
+                };
+
+                if offset + len == available {
+                    state.offset.store(0, Ordering::SeqCst);
+                    state.available.store(0, Ordering::SeqCst);
+                    state.pump.wake();
+                } else {
+                    state.offset.store(offset + len, Ordering::SeqCst);
+                }
+
+                return Ok(len / mem::size_of::<AdcMeasurement>());
+            }
+
+            if !state.pump.is_running() {
+                return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+            }
+
+            state.notification.wait().await;
+        }
+    }
+
+    pub fn release(mut self) -> Result<ADC, EspError> {
+        if self.started {
+            self.stop()?;
+        }
+
+        esp!(unsafe { adc_digi_deinitialize() })?;
+
+        TAKEN.store(false, Ordering::SeqCst);
+
+        Ok(self.adc)
+    }
+}
+
+/// One of the two hardware IIR filters of the digital controller
+#[cfg(any(esp32s2, esp32s3))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IirFilter {
+    Filter0,
+    Filter1,
+}
+
+#[cfg(any(esp32s2, esp32s3))]
+impl From<IirFilter> for adc_digi_filter_idx_t {
+    fn from(filter: IirFilter) -> Self {
+        match filter {
+            IirFilter::Filter0 => adc_digi_filter_idx_t_ADC_DIGI_FILTER_IDX0,
+            IirFilter::Filter1 => adc_digi_filter_idx_t_ADC_DIGI_FILTER_IDX1,
+        }
+    }
+}
+
+/// Weight of the previous output of a hardware IIR filter: with a coefficient of `k`,
+/// each new measurement contributes `1 / k` to the output
+#[cfg(any(esp32s2, esp32s3))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IirCoefficient {
+    K2,
+    K4,
+    K8,
+    K16,
+    K64,
+}
+
+#[cfg(any(esp32s2, esp32s3))]
+impl From<IirCoefficient> for adc_digi_filter_mode_t {
+    fn from(coefficient: IirCoefficient) -> Self {
+        match coefficient {
+            IirCoefficient::K2 => adc_digi_filter_mode_t_ADC_DIGI_FILTER_IIR_2,
+            IirCoefficient::K4 => adc_digi_filter_mode_t_ADC_DIGI_FILTER_IIR_4,
+            IirCoefficient::K8 => adc_digi_filter_mode_t_ADC_DIGI_FILTER_IIR_8,
+            IirCoefficient::K16 => adc_digi_filter_mode_t_ADC_DIGI_FILTER_IIR_16,
+            IirCoefficient::K64 => adc_digi_filter_mode_t_ADC_DIGI_FILTER_IIR_64,
+        }
+    }
+}
+
+pub(super) fn unit_index(unit: adc_unit_t) -> u8 {
+    if unit == adc_unit_t_ADC_UNIT_1 {
+        0
+    } else {
+        1
+    }
+}
+
+/// Returns the number of bytes read, which is 0 on timeout
+fn read_bytes(buf: *mut u8, len: usize, timeout_ms: u32) -> Result<usize, EspError> {
+    let mut read = 0;
+
+    match unsafe { adc_digi_read_bytes(buf, len as _, &mut read, timeout_ms) } {
+        ESP_OK => Ok(read as usize),
+        ESP_ERR_TIMEOUT => Ok(0),
+        err => Err(EspError::from(err).unwrap()),
+    }
+}
+
+/// State shared with the task which reads frames from the driver for async readers
+struct AsyncState {
+    pump: Pump,
+    /// Number of bytes in `buffer`, or 0 when the task may refill it
+    available: AtomicUsize,
+    /// Number of bytes of `buffer` already consumed by readers
+    offset: AtomicUsize,
+    frame_size: AtomicUsize,
+    buffer: UnsafeCell<[u8; ASYNC_BUFFER_SIZE]>,
+    notification: HalIsrNotification,
+}
+
+unsafe impl Sync for AsyncState {}
+
+static ASYNC_STATE: AsyncState = AsyncState {
+    pump: Pump::new(),
+    available: AtomicUsize::new(0),
+    offset: AtomicUsize::new(0),
+    frame_size: AtomicUsize::new(0),
+    buffer: UnsafeCell::new([0; ASYNC_BUFFER_SIZE]),
+    notification: HalIsrNotification::new(),
+};
+
+impl AsyncState {
+    fn start(frame_size: usize, async_task: &TaskConfig) -> Result<(), EspError> {
+        let state = &ASYNC_STATE;
+
+        if state.pump.is_running() {
+            return Ok(());
+        }
+
+        state.available.store(0, Ordering::SeqCst);
+        state.offset.store(0, Ordering::SeqCst);
+        state.frame_size.store(
+            core::cmp::min(frame_size, ASYNC_BUFFER_SIZE),
+            Ordering::SeqCst,
+        );
+        state.notification.reset();
+
+        state.pump.start(async_task, Self::fill)?;
+
+        Ok(())
+    }
+
+    fn stop() {
+        let state = &ASYNC_STATE;
+
+        if state.pump.stop() {
+            // Pending reads fail with `ESP_ERR_INVALID_STATE` once woken up
+            state.notification.notify();
+        }
+    }
+
+    /// Polls the driver for a frame of up to `ASYNC_BUFFER_SIZE` bytes once the readers have
+    /// consumed the previous one, and notifies the async readers
+    fn fill() {
+        let state = &ASYNC_STATE;
+
+        if state.available.load(Ordering::SeqCst) > 0 {
+            // Readers wake the task once they have consumed the buffer; new frames
+            // accumulate in the driver buffer meanwhile
+            state
+                .pump
+                .wait(Duration::from_millis(ASYNC_TASK_POLL_MS as u64));
+            return;
+        }
+
+        let read = read_bytes(
+            state.buffer.get() as *mut u8,
+            state.frame_size.load(Ordering::SeqCst),
+            ASYNC_TASK_POLL_MS,
+        );
+
+        if let Ok(len) = read {
+            if len > 0 {
+                state.available.store(len, Ordering::SeqCst);
+                state.notification.notify();
+            }
+        }
+    }
+}
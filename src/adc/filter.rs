@@ -0,0 +1,366 @@
+//! Software filters combining a burst of one-shot readings into one value
+
+use esp_idf_sys::*;
+
+/// Maximum number of samples combined by [`PoweredAdc::read_averaged`](super::PoweredAdc::read_averaged)
+pub const MAX_AVERAGED_SAMPLES: usize = 256;
+
+/// Maximum number of [`Stage`]s in a [`Pipeline`]
+pub const MAX_PIPELINE_STAGES: usize = 4;
+
+/// Widest window of [`Stage::MovingMedian`]
+pub const MAX_MEDIAN_WINDOW: usize = 15;
+
+/// How a [`Pipeline`] finally combines a burst of raw samples into one reading
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Filter {
+    /// Arithmetic mean of all samples
+    Mean,
+    /// Median of all samples; robust against single outliers
+    Median,
+    /// Arithmetic mean after dropping the given number of lowest and of highest samples
+    TrimmedMean(usize),
+    /// Exponential moving average over the samples in the order they were taken,
+    /// each new sample being weighted with `1 / 2^shift`
+    ///
+    /// This is computed in software. The hardware IIR filter of the ESP32-S2 and ESP32-S3
+    /// belongs to the digital controller and only filters its own conversions, whereas
+    /// one-shot reads go through the RTC controller, so it is only available as
+    /// [`ContinuousAdc::set_iir_filter`](super::continuous::ContinuousAdc::set_iir_filter).
+    Iir(u8),
+}
+
+impl Filter {
+    /// Combine `samples` into one value; the samples may be reordered
+    ///
+    /// Returns `None` if there are no samples, or if all samples are trimmed away.
+    pub fn apply(&self, samples: &mut [u16]) -> Option<u16> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        match *self {
+            Self::Mean => Some(Self::mean(samples)),
+            Self::Median => {
+                samples.sort_unstable();
+
+                let middle = samples.len() / 2;
+
+                if samples.len() % 2 == 0 {
+                    Some(((samples[middle - 1] as u32 + samples[middle] as u32) / 2) as u16)
+                } else {
+                    Some(samples[middle])
+                }
+            }
+            Self::TrimmedMean(trim) => {
+                if samples.len() <= trim * 2 {
+                    return None;
+                }
+
+                samples.sort_unstable();
+
+                Some(Self::mean(&samples[trim..samples.len() - trim]))
+            }
+            Self::Iir(shift) => {
+                Stage::Iir(shift).apply(samples);
+
+                samples.last().copied()
+            }
+        }
+    }
+
+    fn mean(samples: &[u16]) -> u16 {
+        let sum: u32 = samples.iter().map(|sample| *sample as u32).sum();
+
+        ((sum + samples.len() as u32 / 2) / samples.len() as u32) as u16
+    }
+}
+
+/// A step of a [`Pipeline`], which transforms a burst of raw samples
+/// while keeping them in the order they were taken
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stage {
+    /// Drop the given number of lowest and of highest samples
+    Trim(usize),
+    /// Replace each sample with the median of the window of the given width
+    /// (up to [`MAX_MEDIAN_WINDOW`]) ending with it, dropping the first `width - 1` samples;
+    /// removes spikes shorter than half the window
+    MovingMedian(usize),
+    /// Replace each sample with the exponential moving average up to it,
+    /// each new sample being weighted with `1 / 2^shift`
+    Iir(u8),
+}
+
+impl Stage {
+    /// Transform `samples`, which must not be more than [`MAX_AVERAGED_SAMPLES`],
+    /// and return how many are left at their start
+    fn apply(&self, samples: &mut [u16]) -> usize {
+        let len = samples.len();
+
+        match *self {
+            Self::Trim(trim) => {
+                if len <= trim * 2 {
+                    return 0;
+                }
+
+                let mut sorted = [0_u16; MAX_AVERAGED_SAMPLES];
+                let sorted = &mut sorted[..len];
+
+                sorted.copy_from_slice(samples);
+                sorted.sort_unstable();
+
+                // Samples equal to the lowest and highest kept values are only
+                // partially dropped, the earliest ones first
+                let low = sorted[trim];
+                let high = sorted[len - 1 - trim];
+                let mut drop_low = trim - sorted.iter().filter(|s| **s < low).count();
+                let mut drop_high = trim - sorted.iter().filter(|s| **s > high).count();
+
+                let mut kept = 0;
+
+                for index in 0..len {
+                    let sample = samples[index];
+
+                    if sample < low || sample > high {
+                        continue;
+                    } else if sample == low && drop_low > 0 {
+                        drop_low -= 1;
+                    } else if sample == high && drop_high > 0 {
+                        drop_high -= 1;
+                    } else {
+                        samples[kept] = sample;
+                        kept += 1;
+                    }
+                }
+
+                kept
+            }
+            Self::MovingMedian(width) => {
+                let width = width.clamp(1, MAX_MEDIAN_WINDOW);
+
+                if len < width {
+                    return 0;
+                }
+
+                let mut window = [0_u16; MAX_MEDIAN_WINDOW];
+                let window = &mut window[..width];
+
+                // Each output replaces the first sample of its window, which is not needed anymore
+                for index in 0..=len - width {
+                    window.copy_from_slice(&samples[index..index + width]);
+                    window.sort_unstable();
+
+                    samples[index] = if width % 2 == 0 {
+                        ((window[width / 2 - 1] as u32 + window[width / 2] as u32) / 2) as u16
+                    } else {
+                        window[width / 2]
+                    };
+                }
+
+                len - width + 1
+            }
+            Self::Iir(shift) => {
+                if len == 0 {
+                    return 0;
+                }
+
+                // Fixed point with 16 fractional bits to avoid losing the small increments
+                let shift = core::cmp::min(shift, 15);
+                let mut value = (samples[0] as i64) << 16;
+
+                for sample in &mut samples[1..] {
+                    value += (((*sample as i64) << 16) - value) >> shift;
+
+                    *sample = ((value + (1 << 15)) >> 16) as u16;
+                }
+
+                len
+            }
+        }
+    }
+}
+
+/// How [`PoweredAdc::read_averaged`](super::PoweredAdc::read_averaged) combines a burst of raw samples into one reading:
+/// a chain of [`Stage`]s, run in the order they were added, followed by a [`Filter`]
+///
+/// A [`Filter`] converts into a pipeline without stages.
+///
+/// ```
+/// use esp_idf_hal::adc::{Filter, Pipeline, Stage};
+///
+/// // Remove spikes, then drop the two extremes on each side and average the rest
+/// let pipeline = Pipeline::new(Filter::Mean)
+///     .stage(Stage::MovingMedian(3))?
+///     .stage(Stage::Trim(2))?;
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Pipeline {
+    stages: [Stage; MAX_PIPELINE_STAGES],
+    len: usize,
+    filter: Filter,
+}
+
+impl Pipeline {
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            stages: [Stage::Trim(0); MAX_PIPELINE_STAGES],
+            len: 0,
+            filter,
+        }
+    }
+
+    /// Append `stage`
+    ///
+    /// Fails with `ESP_ERR_NO_MEM` if the pipeline already has [`MAX_PIPELINE_STAGES`] stages.
+    pub fn stage(mut self, stage: Stage) -> Result<Self, EspError> {
+        if self.len == MAX_PIPELINE_STAGES {
+            return Err(EspError::from(ESP_ERR_NO_MEM as i32).unwrap());
+        }
+
+        self.stages[self.len] = stage;
+        self.len += 1;
+
+        Ok(self)
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages[..self.len]
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Combine `samples` into one value; the samples are overwritten
+    ///
+    /// Returns `None` if there are no samples or more than [`MAX_AVERAGED_SAMPLES`],
+    /// or if the stages or the filter leave none.
+    pub fn apply(&self, samples: &mut [u16]) -> Option<u16> {
+        if samples.len() > MAX_AVERAGED_SAMPLES {
+            return None;
+        }
+
+        let len = self
+            .stages()
+            .iter()
+            .fold(samples.len(), |len, stage| stage.apply(&mut samples[..len]));
+
+        self.filter.apply(&mut samples[..len])
+    }
+}
+
+impl From<Filter> for Pipeline {
+    fn from(filter: Filter) -> Self {
+        Self::new(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_reject_empty_bursts() {
+        for filter in [
+            Filter::Mean,
+            Filter::Median,
+            Filter::TrimmedMean(0),
+            Filter::Iir(2),
+        ] {
+            assert_eq!(filter.apply(&mut []), None);
+        }
+    }
+
+    #[test]
+    fn mean_rounds_to_nearest() {
+        assert_eq!(Filter::Mean.apply(&mut [1, 2]), Some(2));
+        assert_eq!(Filter::Mean.apply(&mut [10, 20, 31]), Some(20));
+    }
+
+    #[test]
+    fn median_of_odd_and_even_bursts() {
+        assert_eq!(Filter::Median.apply(&mut [5, 1, 3]), Some(3));
+        assert_eq!(Filter::Median.apply(&mut [4, 1, 3, 2]), Some(2));
+    }
+
+    #[test]
+    fn trimmed_mean_drops_extremes() {
+        assert_eq!(
+            Filter::TrimmedMean(1).apply(&mut [100, 10, 12, 14, 0]),
+            Some(12)
+        );
+        assert_eq!(Filter::TrimmedMean(1).apply(&mut [1, 2]), None);
+    }
+
+    #[test]
+    fn iir_filter_returns_the_last_average() {
+        assert_eq!(Filter::Iir(1).apply(&mut [0, 1000, 1000]), Some(750));
+    }
+
+    #[test]
+    fn trim_stage_keeps_order_and_drops_ties_once() {
+        let mut samples = [5, 9, 1, 5, 7];
+
+        assert_eq!(Stage::Trim(1).apply(&mut samples), 3);
+        assert_eq!(samples[..3], [5, 5, 7]);
+
+        let mut samples = [3; 4];
+
+        assert_eq!(Stage::Trim(1).apply(&mut samples), 2);
+        assert_eq!(Stage::Trim(2).apply(&mut samples), 0);
+    }
+
+    #[test]
+    fn moving_median_stage_removes_spikes() {
+        let mut samples = [1, 100, 2, 3, 4];
+
+        assert_eq!(Stage::MovingMedian(3).apply(&mut samples), 3);
+        assert_eq!(samples[..3], [2, 3, 3]);
+
+        assert_eq!(Stage::MovingMedian(6).apply(&mut samples), 0);
+
+        let mut samples = [7, 8];
+
+        assert_eq!(Stage::MovingMedian(0).apply(&mut samples), 2);
+        assert_eq!(samples, [7, 8]);
+    }
+
+    #[test]
+    fn iir_stage_averages_in_place() {
+        let mut samples = [0, 400, 400];
+
+        assert_eq!(Stage::Iir(2).apply(&mut samples), 3);
+        assert_eq!(samples, [0, 100, 175]);
+    }
+
+    #[test]
+    fn pipeline_runs_stages_in_order_before_the_filter() {
+        let pipeline = Pipeline::new(Filter::Mean)
+            .stage(Stage::MovingMedian(3))
+            .unwrap()
+            .stage(Stage::Trim(1))
+            .unwrap();
+
+        assert_eq!(pipeline.stages(), [Stage::MovingMedian(3), Stage::Trim(1)]);
+        assert_eq!(
+            pipeline.apply(&mut [10, 10, 200, 10, 12, 11, 0, 11]),
+            Some(11)
+        );
+        assert_eq!(pipeline.apply(&mut [0; MAX_AVERAGED_SAMPLES + 1]), None);
+        assert_eq!(pipeline.apply(&mut [1, 2]), None);
+    }
+
+    #[test]
+    fn pipeline_rejects_too_many_stages() {
+        let pipeline = (0..MAX_PIPELINE_STAGES)
+            .fold(Pipeline::from(Filter::Median), |pipeline, _| {
+                pipeline.stage(Stage::Iir(1)).unwrap()
+            });
+
+        assert_eq!(pipeline.stages().len(), MAX_PIPELINE_STAGES);
+        assert_eq!(
+            pipeline.stage(Stage::Iir(1)).unwrap_err().code(),
+            ESP_ERR_NO_MEM as i32
+        );
+    }
+}
@@ -0,0 +1,622 @@
+//! ADC threshold monitor.
+//!
+//! Watches one ADC channel and reports when its measurements rise to a high threshold or
+//! fall to a low threshold, e.g. to detect a discharged battery without polling the ADC.
+//!
+//! The channel is sampled by the digital controller in continuous mode, and its hardware
+//! monitor compares each conversion with the thresholds and raises an interrupt on a crossing.
+//! The ESP32-S2 monitor only compares in one direction at a time, so only one of the
+//! thresholds can be set there.
+//!
+//! # Example
+//!
+//! ```
+//! use esp_idf_hal::adc::monitor;
+//! use esp_idf_hal::prelude::*;
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let pin = peripherals.pins.gpio4.into_analog_atten_11db()?;
+//!
+//! let config = monitor::config::Config::new().low(1800).high(3900).hysteresis(50);
+//!
+//! let mut monitor = monitor::AdcMonitor::new(peripherals.adc1, &pin, &config)?;
+//!
+//! match monitor.wait().await? {
+//!     monitor::Event::Low => info!("Battery low"),
+//!     monitor::Event::High => info!("Charger connected"),
+//! }
+//! ```
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "alloc")]
+use core::cell::UnsafeCell;
+use core::mem;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use esp_idf_sys::*;
+
+use crate::interrupt::asynch::HalIsrNotification;
+use crate::interrupt::CriticalSection;
+
+use super::continuous::{self, AdcMeasurement, Channels, ContinuousAdc};
+use super::{Adc, Analog};
+
+/// Size in measurements of the DMA frames, which are not read but have to be configured
+const MONITOR_FRAME_LEN: usize = 16;
+
+/// Marks an unset threshold in [`MonitorState`]
+const NO_THRESHOLD: u32 = u32::MAX;
+
+pub mod config {
+    use crate::units::*;
+
+    /// Thresholds are compared with raw conversion results
+    #[derive(Debug, Copy, Clone)]
+    pub struct Config {
+        /// Report measurements at or below this value
+        pub low: Option<u16>,
+        /// Report measurements at or above this value
+        pub high: Option<u16>,
+        /// How far a measurement has to move back inside the thresholds
+        /// before the same threshold is reported again
+        pub hysteresis: u16,
+        /// Conversions per second
+        pub sample_freq: Hertz,
+    }
+
+    impl Config {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn low(mut self, low: u16) -> Self {
+            self.low = Some(low);
+            self
+        }
+
+        #[must_use]
+        pub fn high(mut self, high: u16) -> Self {
+            self.high = Some(high);
+            self
+        }
+
+        #[must_use]
+        pub fn hysteresis(mut self, hysteresis: u16) -> Self {
+            self.hysteresis = hysteresis;
+            self
+        }
+
+        #[must_use]
+        pub fn sample_freq(mut self, sample_freq: Hertz) -> Self {
+            self.sample_freq = sample_freq;
+            self
+        }
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                low: None,
+                high: None,
+                hysteresis: 0,
+                // The lowest sample frequency supported by the digital controller
+                sample_freq: Hertz(1_000),
+            }
+        }
+    }
+}
+
+/// A threshold crossing
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    Low,
+    High,
+}
+
+impl Event {
+    fn encode(self) -> u8 {
+        match self {
+            Self::Low => 1,
+            Self::High => 2,
+        }
+    }
+
+    fn decode(pending: u8) -> Option<Self> {
+        match pending {
+            1 => Some(Self::Low),
+            2 => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+
+/// ADC threshold monitor driver
+///
+/// Only one monitor can exist at a time, as it takes over the digital controller.
+pub struct AdcMonitor<ADC: Adc> {
+    adc: ContinuousAdc<ADC>,
+    interrupt: hw::Interrupt,
+}
+
+impl<ADC: Adc> AdcMonitor<ADC> {
+    /// Start monitoring the channel of `pin`
+    pub fn new<AN, PIN>(adc: ADC, pin: &PIN, config: &config::Config) -> Result<Self, EspError>
+    where
+        AN: Analog<ADC>,
+        PIN: embedded_hal_0_2::adc::Channel<AN, ID = u8>,
+    {
+        check_thresholds(config.low, config.high)?;
+
+        let channels = Channels::new().channel(pin);
+        let continuous_config = continuous::config::Config::new()
+            .sample_freq(config.sample_freq)
+            .frame_size(MONITOR_FRAME_LEN * mem::size_of::<AdcMeasurement>());
+
+        let mut adc = ContinuousAdc::new(adc, &channels, &continuous_config)?;
+
+        let state = &MONITOR_STATE;
+
+        state
+            .hysteresis
+            .store(config.hysteresis as u32, Ordering::SeqCst);
+        state.pending.store(0, Ordering::SeqCst);
+        state.notification.reset();
+
+        let interrupt = match adc
+            .start()
+            .and_then(|_| hw::start(ADC::unit(), PIN::channel(), handle_isr))
+        {
+            Ok(interrupt) => interrupt,
+            Err(e) => {
+                let _ = adc.release();
+
+                return Err(e);
+            }
+        };
+
+        state.active.store(true, Ordering::SeqCst);
+        state.set_thresholds(config.low, config.high);
+
+        Ok(Self { adc, interrupt })
+    }
+
+    /// Change the thresholds; a threshold the measurements are currently beyond
+    /// is reported again
+    pub fn set_thresholds(&mut self, low: Option<u16>, high: Option<u16>) -> Result<(), EspError> {
+        check_thresholds(low, high)?;
+
+        MONITOR_STATE.set_thresholds(low, high);
+
+        Ok(())
+    }
+
+    /// Deliver threshold crossings to `callback`
+    ///
+    /// Crossings are delivered until [`AdcMonitor::unsubscribe`] is called.
+    ///
+    /// # Safety
+    ///
+    /// The callback is called from the monitor ISR, within a critical section, so it
+    /// must not block and may only use ISR-safe ESP-IDF APIs.
+    #[cfg(feature = "alloc")]
+    pub unsafe fn subscribe(&mut self, callback: impl FnMut(Event) + Send + 'static) {
+        MONITOR_STATE.set_callback(Some(Box::new(callback)));
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn unsubscribe(&mut self) {
+        MONITOR_STATE.set_callback(None);
+    }
+
+    /// Wait for a threshold to be crossed, returning the latest crossing since the last call
+    pub async fn wait(&self) -> Result<Event, EspError> {
+        let state = &MONITOR_STATE;
+
+        loop {
+            if let Some(event) = Event::decode(state.pending.swap(0, Ordering::SeqCst)) {
+                return Ok(event);
+            }
+
+            if !state.active.load(Ordering::SeqCst) {
+                return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+            }
+
+            state.notification.wait().await;
+        }
+    }
+
+    pub fn release(self) -> Result<ADC, EspError> {
+        let state = &MONITOR_STATE;
+
+        // Disables the monitor and frees its interrupt
+        mem::drop(self.interrupt);
+
+        state.active.store(false, Ordering::SeqCst);
+
+        #[cfg(feature = "alloc")]
+        state.set_callback(None);
+
+        // Pending waits fail with `ESP_ERR_INVALID_STATE` once woken up
+        state.notification.notify();
+
+        self.adc.release()
+    }
+}
+
+fn check_thresholds(low: Option<u16>, high: Option<u16>) -> Result<(), EspError> {
+    match (low, high) {
+        (_, Some(0)) => Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap()),
+        #[cfg(esp32s2)]
+        (Some(_), Some(_)) => Err(EspError::from(ESP_ERR_NOT_SUPPORTED as i32).unwrap()),
+        (Some(low), Some(high)) if low >= high => {
+            Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Which side of the thresholds the measurements are on
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Zone {
+    Inside,
+    Low,
+    High,
+}
+
+impl From<u8> for Zone {
+    fn from(zone: u8) -> Self {
+        match zone {
+            1 => Self::Low,
+            2 => Self::High,
+            _ => Self::Inside,
+        }
+    }
+}
+
+/// State shared with the monitor ISR; the thresholds, the zone and the monitor
+/// registers are only changed within `cs`
+struct MonitorState {
+    cs: CriticalSection,
+    active: AtomicBool,
+    low: AtomicU32,
+    high: AtomicU32,
+    hysteresis: AtomicU32,
+    /// Which side of the thresholds the measurements are on
+    zone: AtomicU8,
+    /// The latest [`Event`] not yet returned by [`AdcMonitor::wait`], encoded
+    pending: AtomicU8,
+    #[cfg(feature = "alloc")]
+    #[allow(clippy::type_complexity)]
+    callback: UnsafeCell<Option<Box<dyn FnMut(Event) + Send>>>,
+    notification: HalIsrNotification,
+}
+
+unsafe impl Sync for MonitorState {}
+
+static MONITOR_STATE: MonitorState = MonitorState {
+    cs: CriticalSection::new(),
+    active: AtomicBool::new(false),
+    low: AtomicU32::new(NO_THRESHOLD),
+    high: AtomicU32::new(NO_THRESHOLD),
+    hysteresis: AtomicU32::new(0),
+    zone: AtomicU8::new(Zone::Inside as u8),
+    pending: AtomicU8::new(0),
+    #[cfg(feature = "alloc")]
+    callback: UnsafeCell::new(None),
+    notification: HalIsrNotification::new(),
+};
+
+impl MonitorState {
+    fn set_thresholds(&self, low: Option<u16>, high: Option<u16>) {
+        let _guard = self.cs.enter();
+
+        self.low
+            .store(low.map_or(NO_THRESHOLD, |low| low as u32), Ordering::SeqCst);
+        self.high.store(
+            high.map_or(NO_THRESHOLD, |high| high as u32),
+            Ordering::SeqCst,
+        );
+        self.zone.store(Zone::Inside as u8, Ordering::SeqCst);
+
+        self.arm();
+    }
+
+    #[cfg(feature = "alloc")]
+    #[allow(clippy::type_complexity)]
+    fn set_callback(&self, callback: Option<Box<dyn FnMut(Event) + Send>>) {
+        let previous = {
+            let _guard = self.cs.enter();
+
+            mem::replace(unsafe { &mut *self.callback.get() }, callback)
+        };
+
+        // Dropped outside of the critical section
+        mem::drop(previous);
+    }
+
+    /// Program the monitor to interrupt once the measurements leave the current zone;
+    /// must be called within `cs`
+    fn arm(&self) {
+        let low = self.low.load(Ordering::SeqCst);
+        let high = self.high.load(Ordering::SeqCst);
+        let hysteresis = self.hysteresis.load(Ordering::SeqCst);
+
+        // The hardware compares strictly
+        let (below, above) = match Zone::from(self.zone.load(Ordering::SeqCst)) {
+            Zone::Inside => (
+                (low != NO_THRESHOLD).then(|| low + 1),
+                (high != NO_THRESHOLD).then(|| high - 1),
+            ),
+            Zone::High => (
+                high.checked_sub(hysteresis).filter(|below| *below > 0),
+                None,
+            ),
+            Zone::Low => (None, Some(low + hysteresis)),
+        };
+
+        unsafe { hw::arm(below, above) };
+    }
+}
+
+/// Moves to the zone the measurements entered, reports the threshold crossings
+/// to the subscribed callback and async waiters, and re-arms the monitor
+unsafe extern "C" fn handle_isr(_arg: *mut c_types::c_void) {
+    let state = &MONITOR_STATE;
+
+    let _guard = state.cs.enter();
+
+    let (below, above) = hw::triggered();
+
+    let (zone, event) = match Zone::from(state.zone.load(Ordering::SeqCst)) {
+        Zone::Inside if above => (Zone::High, Some(Event::High)),
+        Zone::Inside if below => (Zone::Low, Some(Event::Low)),
+        Zone::High | Zone::Low if below || above => (Zone::Inside, None),
+        zone => (zone, None),
+    };
+
+    state.zone.store(zone as u8, Ordering::SeqCst);
+    state.arm();
+
+    if let Some(event) = event {
+        state.pending.store(event.encode(), Ordering::SeqCst);
+
+        #[cfg(feature = "alloc")]
+        if let Some(callback) = (*state.callback.get()).as_mut() {
+            callback(event);
+        }
+
+        state.notification.notify();
+    }
+}
+
+/// Threshold monitor 0 of the digital controller, driven through its registers
+/// (see the ADC chapter of the Technical Reference Manual)
+///
+/// The register addresses and fields come from the `soc/apb_saradc_reg.h` of the chip,
+/// as the register maps of the ESP32-S3 and ESP32-C3 differ.
+#[cfg(any(esp32s3, esp32c3))]
+mod hw {
+    use core::ptr;
+
+    use esp_idf_sys::*;
+
+    use crate::interrupt::{self, InterruptFlags, InterruptHandle};
+
+    /// Unit in bit 3 and channel in bits 0-2
+    const THRES0_CHANNEL: u32 = APB_SARADC_THRES0_CHANNEL_V << APB_SARADC_THRES0_CHANNEL_S;
+    const THRES0_HIGH: u32 = APB_SARADC_THRES0_HIGH_V << APB_SARADC_THRES0_HIGH_S;
+    const THRES0_LOW: u32 = APB_SARADC_THRES0_LOW_V << APB_SARADC_THRES0_LOW_S;
+
+    const THRES0_EN: u32 = APB_SARADC_THRES0_EN_V << APB_SARADC_THRES0_EN_S;
+
+    const THRES0_LOW_INT_ENA: u32 =
+        APB_SARADC_THRES0_LOW_INT_ENA_V << APB_SARADC_THRES0_LOW_INT_ENA_S;
+    const THRES0_HIGH_INT_ENA: u32 =
+        APB_SARADC_THRES0_HIGH_INT_ENA_V << APB_SARADC_THRES0_HIGH_INT_ENA_S;
+    const THRES0_INT_ENA: u32 = THRES0_LOW_INT_ENA | THRES0_HIGH_INT_ENA;
+
+    const THRES0_LOW_INT_ST: u32 = APB_SARADC_THRES0_LOW_INT_ST_V << APB_SARADC_THRES0_LOW_INT_ST_S;
+    const THRES0_HIGH_INT_ST: u32 =
+        APB_SARADC_THRES0_HIGH_INT_ST_V << APB_SARADC_THRES0_HIGH_INT_ST_S;
+
+    const THRES0_LOW_INT_CLR: u32 =
+        APB_SARADC_THRES0_LOW_INT_CLR_V << APB_SARADC_THRES0_LOW_INT_CLR_S;
+    const THRES0_HIGH_INT_CLR: u32 =
+        APB_SARADC_THRES0_HIGH_INT_CLR_V << APB_SARADC_THRES0_HIGH_INT_CLR_S;
+    const THRES0_INT_CLR: u32 = THRES0_LOW_INT_CLR | THRES0_HIGH_INT_CLR;
+
+    /// The monitor interrupt; dropping it disables the monitor
+    pub struct Interrupt(#[allow(dead_code)] InterruptHandle);
+
+    pub fn start(
+        unit: adc_unit_t,
+        channel: u8,
+        handler: unsafe extern "C" fn(*mut c_types::c_void),
+    ) -> Result<Interrupt, EspError> {
+        unsafe {
+            modify(APB_SARADC_INT_ENA_REG, THRES0_INT_ENA, 0);
+            write(APB_SARADC_INT_CLR_REG, THRES0_INT_CLR);
+
+            modify(
+                APB_SARADC_THRES0_CTRL_REG,
+                THRES0_CHANNEL,
+                ((super::continuous::unit_index(unit) << 3 | channel) as u32)
+                    << APB_SARADC_THRES0_CHANNEL_S,
+            );
+            modify(APB_SARADC_THRES_CTRL_REG, THRES0_EN, THRES0_EN);
+
+            let handle = interrupt::allocate_raw(
+                periph_interrput_t_ETS_APB_ADC_INTR_SOURCE,
+                InterruptFlags::new(),
+                handler,
+                ptr::null_mut(),
+            );
+
+            if handle.is_err() {
+                modify(APB_SARADC_THRES_CTRL_REG, THRES0_EN, 0);
+            }
+
+            Ok(Interrupt(handle?))
+        }
+    }
+
+    /// Interrupt once a conversion is below `below` or above `above`
+    pub unsafe fn arm(below: Option<u32>, above: Option<u32>) {
+        modify(
+            APB_SARADC_THRES0_CTRL_REG,
+            THRES0_HIGH | THRES0_LOW,
+            (above.unwrap_or(0) & APB_SARADC_THRES0_HIGH_V) << APB_SARADC_THRES0_HIGH_S
+                | (below.unwrap_or(0) & APB_SARADC_THRES0_LOW_V) << APB_SARADC_THRES0_LOW_S,
+        );
+
+        write(APB_SARADC_INT_CLR_REG, THRES0_INT_CLR);
+        modify(
+            APB_SARADC_INT_ENA_REG,
+            THRES0_INT_ENA,
+            below.map_or(0, |_| THRES0_LOW_INT_ENA) | above.map_or(0, |_| THRES0_HIGH_INT_ENA),
+        );
+    }
+
+    /// Disarm the monitor, returning whether it triggered below and above
+    pub unsafe fn triggered() -> (bool, bool) {
+        let status = read(APB_SARADC_INT_ST_REG);
+        let low = status & THRES0_LOW_INT_ST != 0;
+        let high = status & THRES0_HIGH_INT_ST != 0;
+
+        modify(APB_SARADC_INT_ENA_REG, THRES0_INT_ENA, 0);
+        write(APB_SARADC_INT_CLR_REG, THRES0_INT_CLR);
+
+        (low, high)
+    }
+
+    impl Drop for Interrupt {
+        fn drop(&mut self) {
+            unsafe {
+                modify(APB_SARADC_INT_ENA_REG, THRES0_INT_ENA, 0);
+                write(APB_SARADC_INT_CLR_REG, THRES0_INT_CLR);
+                modify(APB_SARADC_THRES_CTRL_REG, THRES0_EN, 0);
+            }
+        }
+    }
+
+    unsafe fn read(reg: u32) -> u32 {
+        ptr::read_volatile(reg as *const u32)
+    }
+
+    unsafe fn write(reg: u32, value: u32) {
+        ptr::write_volatile(reg as *mut u32, value);
+    }
+
+    unsafe fn modify(reg: u32, mask: u32, value: u32) {
+        write(reg, read(reg) & !mask | value & mask);
+    }
+}
+
+/// The monitor of the ADC unit, driven through the ESP-IDF digital controller API;
+/// it compares in one direction at a time
+#[cfg(esp32s2)]
+mod hw {
+    use core::ptr;
+    use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+    use esp_idf_sys::*;
+
+    static UNIT: AtomicU32 = AtomicU32::new(0);
+    static CHANNEL: AtomicU8 = AtomicU8::new(0);
+    /// The armed comparison: 0 if none, 1 below, 2 above
+    static ARMED: AtomicU8 = AtomicU8::new(0);
+
+    /// The monitor interrupt; dropping it disables the monitor
+    pub struct Interrupt(());
+
+    pub fn start(
+        unit: adc_unit_t,
+        channel: u8,
+        handler: unsafe extern "C" fn(*mut c_types::c_void),
+    ) -> Result<Interrupt, EspError> {
+        UNIT.store(unit, Ordering::SeqCst);
+        CHANNEL.store(channel, Ordering::SeqCst);
+        ARMED.store(0, Ordering::SeqCst);
+
+        esp!(unsafe { adc_digi_isr_register(Some(handler), ptr::null_mut(), 0) })?;
+
+        Ok(Interrupt(()))
+    }
+
+    /// Interrupt once a conversion is below `below` or above `above`;
+    /// only one of them can be set
+    pub unsafe fn arm(below: Option<u32>, above: Option<u32>) {
+        debug_assert!(below.is_none() || above.is_none());
+
+        disarm();
+
+        let (armed, mode, threshold) = match (below, above) {
+            (Some(below), _) => (1, adc_digi_monitor_mode_t_ADC_DIGI_MONITOR_LOW, below),
+            (None, Some(above)) => (2, adc_digi_monitor_mode_t_ADC_DIGI_MONITOR_HIGH, above),
+            (None, None) => return,
+        };
+
+        let unit = UNIT.load(Ordering::SeqCst);
+
+        let mut config = adc_digi_monitor_t {
+            adc_unit: unit,
+            channel: CHANNEL.load(Ordering::SeqCst) as _,
+            mode,
+            threshold: threshold as _,
+        };
+
+        adc_digi_monitor_set_config(monitor_index(unit), &mut config);
+        adc_digi_monitor_enable(monitor_index(unit), true);
+        adc_digi_intr_enable(unit, adc_digi_intr_t_ADC_DIGI_INTR_MASK_MONITOR);
+
+        ARMED.store(armed, Ordering::SeqCst);
+    }
+
+    /// Disarm the monitor, returning whether it triggered below and above
+    pub unsafe fn triggered() -> (bool, bool) {
+        let status = adc_digi_intr_get_status(UNIT.load(Ordering::SeqCst));
+        let armed = ARMED.load(Ordering::SeqCst);
+
+        disarm();
+
+        if status & adc_digi_intr_t_ADC_DIGI_INTR_MASK_MONITOR == 0 {
+            (false, false)
+        } else {
+            (armed == 1, armed == 2)
+        }
+    }
+
+    impl Drop for Interrupt {
+        fn drop(&mut self) {
+            unsafe {
+                disarm();
+                adc_digi_isr_deregister();
+            }
+        }
+    }
+
+    unsafe fn disarm() {
+        let unit = UNIT.load(Ordering::SeqCst);
+
+        adc_digi_intr_disable(unit, adc_digi_intr_t_ADC_DIGI_INTR_MASK_MONITOR);
+        adc_digi_intr_clear(unit, adc_digi_intr_t_ADC_DIGI_INTR_MASK_MONITOR);
+        adc_digi_monitor_enable(monitor_index(unit), false);
+
+        ARMED.store(0, Ordering::SeqCst);
+    }
+
+    /// Each unit has its own monitor
+    fn monitor_index(unit: adc_unit_t) -> adc_digi_monitor_idx_t {
+        if unit == adc_unit_t_ADC_UNIT_1 {
+            adc_digi_monitor_idx_t_ADC_DIGI_MONITOR_IDX0
+        } else {
+            adc_digi_monitor_idx_t_ADC_DIGI_MONITOR_IDX1
+        }
+    }
+}
@@ -33,11 +33,37 @@
 //! ```
 
 use core::marker::PhantomData;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
 
 use esp_idf_sys::*;
 
-use crate::delay::portMAX_DELAY;
+use crate::delay::{portMAX_DELAY, TickType};
 use crate::gpio::*;
+use crate::interrupt::asynch::HalIsrNotification;
+use crate::interrupt::task::{Pump, TaskConfig};
+
+pub mod isotp;
+
+/// How long the alert task blocks reading alerts, and so how long stopping it may take
+const ALERT_TASK_POLL_TICKS: TickType_t = 10;
+
+/// Alerts used internally to wake up async senders and receivers
+const ASYNC_ALERTS: Alert = Alert(
+    TWAI_ALERT_RX_DATA
+        | TWAI_ALERT_TX_IDLE
+        | TWAI_ALERT_TX_SUCCESS
+        | TWAI_ALERT_TX_FAILED
+        | TWAI_ALERT_BUS_OFF,
+);
 
 crate::embedded_hal_error!(
     CanError,
@@ -54,6 +80,54 @@ crate::embedded_hal_error!(
 pub mod config {
     use esp_idf_sys::*;
 
+    use crate::interrupt::task::TaskConfig;
+    use crate::interrupt::InterruptFlags;
+    use crate::units::Hertz;
+
+    use super::Alert;
+
+    /// TWAI operating mode
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum Mode {
+        /// Transmit, receive and acknowledge messages
+        Normal,
+        /// Transmit without requiring an acknowledgement, e.g. for self-tests
+        NoAck,
+        /// Only receive; no acknowledgements or error frames are sent
+        ListenOnly,
+    }
+
+    impl Default for Mode {
+        fn default() -> Self {
+            Self::Normal
+        }
+    }
+
+    impl From<Mode> for twai_mode_t {
+        fn from(mode: Mode) -> Self {
+            match mode {
+                Mode::Normal => twai_mode_t_TWAI_MODE_NORMAL,
+                Mode::NoAck => twai_mode_t_TWAI_MODE_NO_ACK,
+                Mode::ListenOnly => twai_mode_t_TWAI_MODE_LISTEN_ONLY,
+            }
+        }
+    }
+
+    /// Clock feeding the TWAI baud rate prescaler (APB clock)
+    pub const SOURCE_CLOCK_HZ: u32 = 80_000_000;
+
+    #[cfg(esp32)]
+    const BRP_MAX: u32 = 128;
+    #[cfg(not(esp32))]
+    const BRP_MAX: u32 = 16384;
+
+    const TSEG_1_MAX: u8 = 16;
+    const TSEG_2_MAX: u8 = 8;
+    const SJW_MAX: u8 = 4;
+
+    /// Lowest and highest accepted sample point, in per mille of the bit time
+    const SAMPLE_POINT_RANGE: core::ops::RangeInclusive<u16> = 500..=900;
+
     /// CAN timing
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     pub enum Timing {
@@ -65,67 +139,151 @@ pub mod config {
         B500K,
         B800K,
         B1M,
+        /// Arbitrary bit timing
+        ///
+        /// One bit lasts `1 + tseg_1 + tseg_2` time quanta of `brp / 80MHz` each, and
+        /// the bus is sampled after `1 + tseg_1` of them.
+        /// Use [`Timing::calculate`] to derive the values from a bit rate and sample point.
+        Custom {
+            brp: u32,
+            tseg_1: u8,
+            tseg_2: u8,
+            sjw: u8,
+            triple_sampling: bool,
+        },
     }
 
-    impl From<Timing> for twai_timing_config_t {
-        fn from(resolution: Timing) -> Self {
-            match resolution {
-                Timing::B25K => twai_timing_config_t {
-                    brp: 128,
-                    tseg_1: 16,
-                    tseg_2: 8,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B50K => twai_timing_config_t {
-                    brp: 80,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B100K => twai_timing_config_t {
-                    brp: 40,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B125K => twai_timing_config_t {
-                    brp: 32,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B250K => twai_timing_config_t {
-                    brp: 16,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B500K => twai_timing_config_t {
-                    brp: 8,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B800K => twai_timing_config_t {
-                    brp: 4,
-                    tseg_1: 16,
-                    tseg_2: 8,
-                    sjw: 3,
-                    triple_sampling: false,
-                },
-                Timing::B1M => twai_timing_config_t {
-                    brp: 4,
-                    tseg_1: 15,
-                    tseg_2: 4,
-                    sjw: 3,
+    impl Timing {
+        /// Calculate a [`Timing::Custom`] for the given bit rate, with the sample point (in per mille
+        /// of the bit time) as close as possible to `sample_point`
+        ///
+        /// Fails with `ESP_ERR_INVALID_ARG` if the bit rate cannot be derived exactly from the
+        /// 80MHz source clock, or if the requested sample point is outside of 50% - 90%.
+        pub fn calculate(bitrate: Hertz, sample_point: u16) -> Result<Self, EspError> {
+            let bitrate = bitrate.0;
+
+            if bitrate == 0 || !SAMPLE_POINT_RANGE.contains(&sample_point) {
+                return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+            }
+
+            let mut best: Option<(u16, Timing)> = None;
+
+            // Prefer more time quanta per bit, as this gives a finer sample point resolution
+            for quanta in (8..=(1 + TSEG_1_MAX as u32 + TSEG_2_MAX as u32)).rev() {
+                let divider = match bitrate.checked_mul(quanta) {
+                    Some(divider) if SOURCE_CLOCK_HZ % divider == 0 => divider,
+                    _ => continue,
+                };
+
+                let brp = SOURCE_CLOCK_HZ / divider;
+                if !(2..=BRP_MAX).contains(&brp) || brp % 2 != 0 {
+                    continue;
+                }
+
+                let sample_quanta = (sample_point as u32 * quanta + 500) / 1000;
+                let tseg_1 = sample_quanta.saturating_sub(1).clamp(1, TSEG_1_MAX as u32);
+                let tseg_2 = quanta - 1 - tseg_1;
+
+                if !(1..=TSEG_2_MAX as u32).contains(&tseg_2) {
+                    continue;
+                }
+
+                let timing = Timing::Custom {
+                    brp,
+                    tseg_1: tseg_1 as u8,
+                    tseg_2: tseg_2 as u8,
+                    sjw: core::cmp::min(SJW_MAX, tseg_2 as u8),
                     triple_sampling: false,
-                },
+                };
+
+                if timing.validate().is_err() {
+                    continue;
+                }
+
+                let error =
+                    (timing.sample_point() as i32 - sample_point as i32).unsigned_abs() as u16;
+
+                if best.map_or(true, |(best_error, _)| error < best_error) {
+                    best = Some((error, timing));
+                }
+            }
+
+            best.map(|(_, timing)| timing)
+                .ok_or_else(|| EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+        }
+
+        /// Check that the prescaler and segment lengths are within the limits of the
+        /// TWAI controller and that the resulting sample point is within 50% - 90%
+        pub fn validate(&self) -> Result<(), EspError> {
+            let (brp, tseg_1, tseg_2, sjw, _) = self.params();
+
+            let valid = (2..=BRP_MAX).contains(&brp)
+                && brp % 2 == 0
+                && (1..=TSEG_1_MAX).contains(&tseg_1)
+                && (1..=TSEG_2_MAX).contains(&tseg_2)
+                && (1..=SJW_MAX).contains(&sjw)
+                && sjw <= tseg_2
+                && SAMPLE_POINT_RANGE.contains(&self.sample_point());
+
+            if valid {
+                Ok(())
+            } else {
+                Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+            }
+        }
+
+        /// Number of time quanta in one bit
+        pub fn quanta(&self) -> u32 {
+            let (_, tseg_1, tseg_2, _, _) = self.params();
+
+            1 + tseg_1 as u32 + tseg_2 as u32
+        }
+
+        /// The resulting bit rate
+        pub fn bitrate(&self) -> Hertz {
+            let (brp, ..) = self.params();
+
+            Hertz(SOURCE_CLOCK_HZ / (brp * self.quanta()))
+        }
+
+        /// The resulting sample point, in per mille of the bit time
+        pub fn sample_point(&self) -> u16 {
+            let (_, tseg_1, ..) = self.params();
+
+            ((1 + tseg_1 as u32) * 1000 / self.quanta()) as u16
+        }
+
+        fn params(&self) -> (u32, u8, u8, u8, bool) {
+            match *self {
+                Timing::B25K => (128, 16, 8, 3, false),
+                Timing::B50K => (80, 15, 4, 3, false),
+                Timing::B100K => (40, 15, 4, 3, false),
+                Timing::B125K => (32, 15, 4, 3, false),
+                Timing::B250K => (16, 15, 4, 3, false),
+                Timing::B500K => (8, 15, 4, 3, false),
+                Timing::B800K => (4, 16, 8, 3, false),
+                Timing::B1M => (4, 15, 4, 3, false),
+                Timing::Custom {
+                    brp,
+                    tseg_1,
+                    tseg_2,
+                    sjw,
+                    triple_sampling,
+                } => (brp, tseg_1, tseg_2, sjw, triple_sampling),
+            }
+        }
+    }
+
+    impl From<Timing> for twai_timing_config_t {
+        fn from(timing: Timing) -> Self {
+            let (brp, tseg_1, tseg_2, sjw, triple_sampling) = timing.params();
+
+            twai_timing_config_t {
+                brp,
+                tseg_1,
+                tseg_2,
+                sjw,
+                triple_sampling,
             }
         }
     }
@@ -182,12 +340,43 @@ pub mod config {
     /// let mask   = 0x7F0;
     /// let f = Filter::Standard { filter, mask };
     /// ```
+    ///
+    /// Two unrelated IDs can be accepted with the dual filter mode,
+    /// and [`FilterBuilder`] computes the filter for an arbitrary set of standard IDs.
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     pub enum Filter {
         // Filter for 11 bit standard CAN IDs
-        Standard { filter: u16, mask: u16 },
+        Standard {
+            filter: u16,
+            mask: u16,
+        },
         // Filter for 29 bit extended CAN IDs
-        Extended { filter: u32, mask: u32 },
+        Extended {
+            filter: u32,
+            mask: u32,
+        },
+        /// Two filters for 11 bit standard CAN IDs; frames matching either of them are accepted
+        ///
+        /// The first filter additionally compares the first data byte with `data`
+        /// according to `data_mask` (use a `data_mask` of `0` to accept any data).
+        Dual {
+            filter1: u16,
+            mask1: u16,
+            filter2: u16,
+            mask2: u16,
+            data: u8,
+            data_mask: u8,
+        },
+        /// Two filters for 29 bit extended CAN IDs; frames matching either of them are accepted
+        ///
+        /// Only the 16 most significant ID bits (bits 28 - 13) are compared,
+        /// so the 13 least significant bits of `mask1` and `mask2` are ignored.
+        ExtendedDual {
+            filter1: u32,
+            mask1: u32,
+            filter2: u32,
+            mask2: u32,
+        },
     }
 
     impl Filter {
@@ -200,6 +389,302 @@ pub mod config {
         pub fn extended_allow_all() -> Self {
             Self::Extended { filter: 0, mask: 0 }
         }
+
+        /// TWAI acceptance code, acceptance mask and whether the single filter mode is used
+        ///
+        /// Notice that bits set in the TWAI acceptance mask mean "do not care".
+        pub fn acceptance(&self) -> (u32, u32, bool) {
+            match *self {
+                Self::Standard { filter, mask } => {
+                    ((filter as u32) << 21, !((mask as u32) << 21), true)
+                }
+                Self::Extended { filter, mask } => (filter << 3, !(mask << 3), true),
+                Self::Dual {
+                    filter1,
+                    mask1,
+                    filter2,
+                    mask2,
+                    data,
+                    data_mask,
+                } => {
+                    // Filter 1: ID in bits 31 - 21, RTR in bit 20, upper data nibble in
+                    // bits 19 - 16 and lower data nibble in bits 3 - 0.
+                    // Filter 2: ID in bits 15 - 5, RTR in bit 4
+                    let pack = |id1: u16, data: u8, id2: u16| {
+                        ((id1 as u32 & 0x7ff) << 21)
+                            | ((data as u32 >> 4) << 16)
+                            | (data as u32 & 0xf)
+                            | ((id2 as u32 & 0x7ff) << 5)
+                    };
+
+                    (
+                        pack(filter1, data, filter2),
+                        !pack(mask1, data_mask, mask2),
+                        false,
+                    )
+                }
+                Self::ExtendedDual {
+                    filter1,
+                    mask1,
+                    filter2,
+                    mask2,
+                } => {
+                    // Filter 1: ID bits 28 - 13 in bits 31 - 16, Filter 2: the same in bits 15 - 0
+                    let pack =
+                        |id1: u32, id2: u32| ((id1 >> 13) & 0xffff) << 16 | ((id2 >> 13) & 0xffff);
+
+                    (pack(filter1, filter2), !pack(mask1, mask2), false)
+                }
+            }
+        }
+    }
+
+    impl From<Filter> for twai_filter_config_t {
+        fn from(filter: Filter) -> Self {
+            let (acceptance_code, acceptance_mask, single_filter) = filter.acceptance();
+
+            twai_filter_config_t {
+                acceptance_code,
+                acceptance_mask,
+                single_filter,
+            }
+        }
+    }
+
+    const STANDARD_ID_COUNT: usize = 0x800;
+    const STANDARD_ID_MASK: u16 = 0x7ff;
+
+    /// Computes a [`Filter`] accepting a set of 11 bit standard CAN IDs
+    ///
+    /// The acceptance filter can only compare ID bits against fixed values, so most sets
+    /// cannot be matched exactly. The builder tries a single filter as well as splitting the
+    /// set among the two filters of [`Filter::Dual`], and picks the candidate which lets the
+    /// fewest extra IDs through; those are reported by [`BuiltFilter::leaked`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use esp_idf_hal::can::config::FilterBuilder;
+    ///
+    /// let built = FilterBuilder::new().id(0x100).range(0x560..=0x56f).build().unwrap();
+    /// assert_eq!(built.leaked_count(), 0);
+    /// ```
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct FilterBuilder {
+        ids: [u32; STANDARD_ID_COUNT / 32],
+    }
+
+    impl FilterBuilder {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        /// Accept `id` (bits above the 11th are ignored)
+        #[must_use]
+        pub fn id(mut self, id: u16) -> Self {
+            let id = (id & STANDARD_ID_MASK) as usize;
+
+            self.ids[id / 32] |= 1 << (id % 32);
+            self
+        }
+
+        /// Accept all IDs in `ids` (bits above the 11th are ignored)
+        #[must_use]
+        pub fn range(self, ids: core::ops::RangeInclusive<u16>) -> Self {
+            let start = *ids.start() & STANDARD_ID_MASK;
+            let end = *ids.end() & STANDARD_ID_MASK;
+
+            (start..=end).fold(self, |builder, id| builder.id(id))
+        }
+
+        /// Compute the filter, or `None` if no ID was added
+        pub fn build(&self) -> Option<BuiltFilter> {
+            let single = self.cover(|_, _| true)?;
+
+            let mut best = (
+                Self::leaked_by(&[single]) - self.len(),
+                [single, single],
+                false,
+            );
+
+            let mut try_split = |in_first: &dyn Fn(usize, u16) -> bool| {
+                if let (Some(first), Some(second)) = (
+                    self.cover(|index, id| in_first(index, id)),
+                    self.cover(|index, id| !in_first(index, id)),
+                ) {
+                    let leaked = Self::leaked_by(&[first, second]) - self.len();
+
+                    if leaked < best.0 {
+                        best = (leaked, [first, second], true);
+                    }
+                }
+            };
+
+            // Split on the value of each ID bit
+            for bit in 0..11 {
+                try_split(&|_, id| id & (1 << bit) == 0);
+            }
+
+            // Split the sorted IDs in two runs. The covers of both runs only change when
+            // an ID adds or removes a "don't care" bit, so only those positions are tried
+            for split in self.split_candidates() {
+                try_split(&|index, _| index < split);
+            }
+
+            let (_, [(filter1, mask1), (filter2, mask2)], dual) = best;
+
+            let filter = if dual {
+                Filter::Dual {
+                    filter1,
+                    mask1,
+                    filter2,
+                    mask2,
+                    data: 0,
+                    data_mask: 0,
+                }
+            } else {
+                Filter::Standard {
+                    filter: filter1,
+                    mask: mask1,
+                }
+            };
+
+            Some(BuiltFilter {
+                filter,
+                covers: [(filter1, mask1), (filter2, mask2)],
+                ids: self.ids,
+            })
+        }
+
+        fn contains(&self, id: u16) -> bool {
+            let id = id as usize;
+
+            self.ids[id / 32] & (1 << (id % 32)) != 0
+        }
+
+        fn iter(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+            (0..STANDARD_ID_COUNT as u16).filter(move |id| self.contains(*id))
+        }
+
+        fn len(&self) -> usize {
+            self.ids.iter().map(|word| word.count_ones() as usize).sum()
+        }
+
+        /// The narrowest `(filter, mask)` pair matching all selected IDs,
+        /// or `None` if no ID is selected
+        fn cover(&self, selected: impl Fn(usize, u16) -> bool) -> Option<(u16, u16)> {
+            let (ones, zeros) = self
+                .iter()
+                .enumerate()
+                .filter(|(index, id)| selected(*index, *id))
+                .fold(None, |cover: Option<(u16, u16)>, (_, id)| {
+                    let (ones, zeros) = cover.unwrap_or((STANDARD_ID_MASK, STANDARD_ID_MASK));
+
+                    Some((ones & id, zeros & !id & STANDARD_ID_MASK))
+                })?;
+
+            Some((ones, ones | zeros))
+        }
+
+        /// Positions at which the cover of the IDs before or after the position changes
+        fn split_candidates(&self) -> impl Iterator<Item = usize> + '_ {
+            let len = self.len();
+
+            let changes = |ids: &mut dyn Iterator<Item = u16>| {
+                let mut cover: Option<(u16, u16)> = None;
+
+                ids.enumerate()
+                    .filter_map(|(index, id)| {
+                        let next = cover.map_or((id, !id & STANDARD_ID_MASK), |(ones, zeros)| {
+                            (ones & id, zeros & !id & STANDARD_ID_MASK)
+                        });
+
+                        let changed = cover.map_or(false, |cover| cover != next);
+                        cover = Some(next);
+
+                        changed.then(|| index)
+                    })
+                    .fold([None; 24], |mut changes, index| {
+                        if let Some(slot) = changes.iter_mut().find(|slot| slot.is_none()) {
+                            *slot = Some(index);
+                        }
+
+                        changes
+                    })
+            };
+
+            let prefix = changes(&mut self.iter());
+            let suffix = changes(&mut self.iter().rev());
+
+            IntoIterator::into_iter(prefix).flatten().chain(
+                IntoIterator::into_iter(suffix)
+                    .flatten()
+                    .map(move |index| len - index),
+            )
+        }
+
+        /// Number of IDs matched by any of the `(filter, mask)` pairs in `covers`
+        fn leaked_by(covers: &[(u16, u16)]) -> usize {
+            let matched = |mask: u16| 1_usize << (11 - mask.count_ones());
+
+            match covers {
+                [(filter1, mask1), (filter2, mask2)] => {
+                    let overlap = if (filter1 ^ filter2) & mask1 & mask2 == 0 {
+                        matched(mask1 | mask2)
+                    } else {
+                        0
+                    };
+
+                    matched(*mask1) + matched(*mask2) - overlap
+                }
+                _ => covers.iter().map(|(_, mask)| matched(*mask)).sum(),
+            }
+        }
+    }
+
+    impl Default for FilterBuilder {
+        fn default() -> Self {
+            Self {
+                ids: [0; STANDARD_ID_COUNT / 32],
+            }
+        }
+    }
+
+    /// A [`Filter`] computed by [`FilterBuilder`]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct BuiltFilter {
+        filter: Filter,
+        covers: [(u16, u16); 2],
+        ids: [u32; STANDARD_ID_COUNT / 32],
+    }
+
+    impl BuiltFilter {
+        pub fn filter(&self) -> Filter {
+            self.filter
+        }
+
+        /// IDs accepted by the filter which were not requested
+        pub fn leaked(&self) -> impl Iterator<Item = u16> + '_ {
+            let builder = FilterBuilder { ids: self.ids };
+
+            (0..STANDARD_ID_COUNT as u16).filter(move |id| {
+                !builder.contains(*id)
+                    && self
+                        .covers
+                        .iter()
+                        .any(|(filter, mask)| (id ^ filter) & mask == 0)
+            })
+        }
+
+        pub fn leaked_count(&self) -> usize {
+            self.leaked().count()
+        }
+    }
+
+    impl From<BuiltFilter> for Filter {
+        fn from(built: BuiltFilter) -> Self {
+            built.filter
+        }
     }
 
     impl Default for Filter {
@@ -208,10 +693,26 @@ pub mod config {
         }
     }
 
-    #[derive(Debug, Copy, Clone, Default)]
+    #[derive(Debug, Copy, Clone)]
     pub struct Config {
         pub timing: Timing,
         pub filter: Filter,
+        pub mode: Mode,
+        pub tx_queue_len: u32,
+        pub rx_queue_len: u32,
+        /// Conditions the driver should report as alerts
+        pub alerts: Alert,
+        /// Divider of the APB clock output on the clkout pin, if one is given.
+        /// Can be 1 or an even number from 2 to 14
+        pub clkout_divider: u32,
+        pub intr_flags: InterruptFlags,
+        /// The task which reads the alerts from the driver once they are delivered to
+        /// a callback or to async waiters, as the driver offers no interrupt hook
+        ///
+        /// The driver raises the alerts from the TWAI interrupt, and the task blocks on them,
+        /// so async senders and receivers are woken up as soon as the interrupt fires. Its
+        /// priority should therefore be above the one of the tasks running the executors.
+        pub alert_task: TaskConfig,
     }
 
     impl Config {
@@ -230,109 +731,1177 @@ pub mod config {
             self.filter = filter;
             self
         }
+
+        #[must_use]
+        pub fn mode(mut self, mode: Mode) -> Self {
+            self.mode = mode;
+            self
+        }
+
+        #[must_use]
+        pub fn tx_queue_len(mut self, tx_queue_len: u32) -> Self {
+            self.tx_queue_len = tx_queue_len;
+            self
+        }
+
+        #[must_use]
+        pub fn rx_queue_len(mut self, rx_queue_len: u32) -> Self {
+            self.rx_queue_len = rx_queue_len;
+            self
+        }
+
+        #[must_use]
+        pub fn alerts(mut self, alerts: Alert) -> Self {
+            self.alerts = alerts;
+            self
+        }
+
+        #[must_use]
+        pub fn clkout_divider(mut self, clkout_divider: u32) -> Self {
+            self.clkout_divider = clkout_divider;
+            self
+        }
+
+        /// Flags for the allocation of the driver interrupt
+        #[must_use]
+        pub fn intr_flags(mut self, flags: InterruptFlags) -> Self {
+            self.intr_flags = flags;
+            self
+        }
+
+        #[must_use]
+        pub fn alert_task(mut self, alert_task: TaskConfig) -> Self {
+            self.alert_task = alert_task;
+            self
+        }
     }
-}
 
-/// CAN abstraction
-pub struct CanBus<TX: OutputPin, RX: InputPin> {
-    can: CAN,
-    tx: TX,
-    rx: RX,
-}
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                timing: Default::default(),
+                filter: Default::default(),
+                mode: Default::default(),
+                tx_queue_len: 5,
+                rx_queue_len: 5,
+                alerts: Alert::NONE,
+                clkout_divider: 0,
+                intr_flags: InterruptFlags::new().level(1),
+                alert_task: TaskConfig::new().name("twai_alerts").stack_size(3072),
+            }
+        }
+    }
 
-unsafe impl<TX: OutputPin, RX: InputPin> Send for CanBus<TX, RX> {}
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const PRESETS: [(Timing, u32); 8] = [
+            (Timing::B25K, 25_000),
+            (Timing::B50K, 50_000),
+            (Timing::B100K, 100_000),
+            (Timing::B125K, 125_000),
+            (Timing::B250K, 250_000),
+            (Timing::B500K, 500_000),
+            (Timing::B800K, 800_000),
+            (Timing::B1M, 1_000_000),
+        ];
+
+        fn custom(brp: u32, tseg_1: u8, tseg_2: u8, sjw: u8) -> Timing {
+            Timing::Custom {
+                brp,
+                tseg_1,
+                tseg_2,
+                sjw,
+                triple_sampling: false,
+            }
+        }
 
-impl<TX: OutputPin, RX: InputPin> CanBus<TX, RX> {
-    pub fn new(can: CAN, tx: TX, rx: RX, config: config::Config) -> Result<Self, EspError> {
-        let general_config = twai_general_config_t {
-            mode: twai_mode_t_TWAI_MODE_NORMAL,
-            tx_io: tx.pin(),
-            rx_io: rx.pin(),
-            clkout_io: -1,
-            bus_off_io: -1,
-            tx_queue_len: 5,
-            rx_queue_len: 5,
-            alerts_enabled: TWAI_ALERT_NONE,
-            clkout_divider: 0,
-            intr_flags: ESP_INTR_FLAG_LEVEL1 as i32,
-        };
+        #[test]
+        fn presets_are_valid() {
+            for (timing, bitrate) in PRESETS {
+                assert!(timing.validate().is_ok(), "{:?}", timing);
+                assert_eq!(timing.bitrate(), Hertz(bitrate), "{:?}", timing);
+                assert!(
+                    SAMPLE_POINT_RANGE.contains(&timing.sample_point()),
+                    "{:?}",
+                    timing
+                );
+            }
+        }
 
-        let timing_config = config.timing.into();
+        #[test]
+        fn calculate_reproduces_preset_bitrates() {
+            for (_, bitrate) in PRESETS {
+                let timing = Timing::calculate(Hertz(bitrate), 800).unwrap();
 
-        // modify filter and mask to be compatible with TWAI acceptance filter
-        let (filter, mask) = match config.filter {
-            config::Filter::Standard { filter, mask } => {
-                ((filter as u32) << 21, !((mask as u32) << 21))
+                assert!(timing.validate().is_ok(), "{:?}", timing);
+                assert_eq!(timing.bitrate(), Hertz(bitrate), "{:?}", timing);
             }
-            config::Filter::Extended { filter, mask } => (filter << 3, !(mask << 3)),
-        };
+        }
 
-        let filter_config = twai_filter_config_t {
-            acceptance_code: filter,
-            acceptance_mask: mask,
-            single_filter: true,
-        };
+        #[test]
+        fn calculate_hits_exact_sample_point() {
+            let timing = Timing::calculate(Hertz(500_000), 875).unwrap();
 
-        esp!(unsafe { twai_driver_install(&general_config, &timing_config, &filter_config) })?;
-        esp!(unsafe { twai_start() })?;
+            assert_eq!(timing.bitrate(), Hertz(500_000));
+            assert_eq!(timing.sample_point(), 875);
+        }
 
-        Ok(Self { can, tx, rx })
-    }
+        #[test]
+        fn calculate_rejects_invalid_requests() {
+            // Not derivable from the 80MHz source clock
+            assert!(Timing::calculate(Hertz(333_333), 800).is_err());
+            // Needs a prescaler below 2
+            assert!(Timing::calculate(Hertz(10_000_000), 800).is_err());
+            assert!(Timing::calculate(Hertz(0), 800).is_err());
+            // Sample point outside of 50% - 90%
+            assert!(Timing::calculate(Hertz(500_000), 499).is_err());
+            assert!(Timing::calculate(Hertz(500_000), 901).is_err());
+        }
 
-    pub fn release(self) -> Result<(CAN, TX, RX), EspError> {
-        esp!(unsafe { twai_stop() })?;
-        esp!(unsafe { twai_driver_uninstall() })?;
+        #[test]
+        fn validate_rejects_out_of_range_custom_timings() {
+            assert!(custom(8, 15, 4, 3).validate().is_ok());
+
+            // Prescaler below 2, odd or above the maximum
+            assert!(custom(0, 15, 4, 3).validate().is_err());
+            assert!(custom(1, 15, 4, 3).validate().is_err());
+            assert!(custom(9, 15, 4, 3).validate().is_err());
+            assert!(custom(BRP_MAX + 2, 15, 4, 3).validate().is_err());
+
+            // Segment lengths
+            assert!(custom(8, 0, 4, 3).validate().is_err());
+            assert!(custom(8, TSEG_1_MAX + 1, 4, 3).validate().is_err());
+            assert!(custom(8, 15, 0, 0).validate().is_err());
+            assert!(custom(8, 15, TSEG_2_MAX + 1, 3).validate().is_err());
+
+            // Synchronization jump width of 0, above the maximum or above `tseg_2`
+            assert!(custom(8, 15, 4, 0).validate().is_err());
+            assert!(custom(8, 15, 8, SJW_MAX + 1).validate().is_err());
+            assert!(custom(8, 15, 2, 3).validate().is_err());
+
+            // Sample points of 20% and 95%
+            assert!(custom(8, 1, 8, 3).validate().is_err());
+            assert!(custom(8, 15, 1, 1).validate().is_err());
+        }
 
-        Ok((self.can, self.tx, self.rx))
-    }
+        /// Whether the TWAI acceptance filter lets a standard data frame with
+        /// identifier `id` and first data byte `data` through
+        fn accepts(filter: Filter, id: u16, data: u8) -> bool {
+            let (code, mask, single) = filter.acceptance();
+            let id = id as u32;
+            let data = data as u32;
 
-    fn transmit_internal(&mut self, frame: &Frame, delay: TickType_t) -> Result<(), EspError> {
-        esp!(unsafe { twai_transmit(&frame.0, delay) })
-    }
+            let matches = |frame: u32, bits: u32| (frame ^ code) & !mask & bits == 0;
 
-    fn receive_internal(&mut self, delay: TickType_t) -> Result<Frame, EspError> {
-        let mut rx_msg = twai_message_t {
-            ..Default::default()
-        };
+            if single {
+                matches(id << 21 | data << 8, u32::MAX)
+            } else {
+                matches(id << 21 | (data >> 4) << 16 | (data & 0xf), 0xffff_000f)
+                    || matches(id << 5, 0x0000_ffff)
+            }
+        }
+
+        fn accepted(filter: Filter) -> impl Iterator<Item = u16> {
+            (0..STANDARD_ID_COUNT as u16).filter(move |id| accepts(filter, *id, 0))
+        }
+
+        #[test]
+        fn single_filters_pack_id_and_mask() {
+            assert_eq!(
+                Filter::Standard {
+                    filter: 0x567,
+                    mask: 0x7ff
+                }
+                .acceptance(),
+                (0x567 << 21, !(0x7ff << 21), true)
+            );
+
+            assert_eq!(
+                Filter::Extended {
+                    filter: 0x1234_5678,
+                    mask: 0x1fff_ff00
+                }
+                .acceptance(),
+                (0x1234_5678 << 3, !(0x1fff_ff00 << 3), true)
+            );
+        }
+
+        #[test]
+        fn dual_filter_packs_ids_and_data() {
+            let filter = Filter::Dual {
+                filter1: 0x123,
+                mask1: 0x7ff,
+                filter2: 0x456,
+                mask2: 0x7f0,
+                data: 0xab,
+                data_mask: 0xf0,
+            };
+
+            assert_eq!(
+                filter.acceptance(),
+                (
+                    0x123 << 21 | 0xa << 16 | 0xb | 0x456 << 5,
+                    !(0x7ff << 21 | 0xf << 16 | 0x7f0 << 5),
+                    false
+                )
+            );
+
+            assert!(accepts(filter, 0x123, 0xa0));
+            assert!(accepts(filter, 0x123, 0xaf));
+            assert!(!accepts(filter, 0x123, 0xb0));
+            assert!(!accepts(filter, 0x124, 0xa0));
+
+            for id in 0x450..=0x45f {
+                assert!(accepts(filter, id, 0));
+            }
+
+            assert!(!accepts(filter, 0x460, 0));
+        }
+
+        #[test]
+        fn extended_dual_filter_packs_upper_id_bits() {
+            let filter = Filter::ExtendedDual {
+                filter1: 0x1234_5678,
+                mask1: 0x1fff_ffff,
+                filter2: 0x0abc_def0,
+                mask2: 0x1fff_e000,
+            };
 
-        match esp_result!(unsafe { twai_receive(&mut rx_msg, delay) }, ()) {
-            Ok(_) => Ok(Frame(rx_msg)),
-            Err(err) => Err(err),
+            assert_eq!(
+                filter.acceptance(),
+                (
+                    (0x1234_5678 >> 13) << 16 | (0x0abc_def0 >> 13),
+                    !(0xffff << 16 | 0xffff),
+                    false
+                )
+            );
+        }
+
+        #[test]
+        fn builder_without_ids_builds_nothing() {
+            assert_eq!(FilterBuilder::new().build(), None);
+        }
+
+        #[test]
+        fn builder_matches_single_id_and_aligned_range_exactly() {
+            let built = FilterBuilder::new().id(0x567).build().unwrap();
+
+            assert_eq!(
+                built.filter(),
+                Filter::Standard {
+                    filter: 0x567,
+                    mask: 0x7ff
+                }
+            );
+            assert_eq!(built.leaked_count(), 0);
+
+            let built = FilterBuilder::new().range(0x560..=0x56f).build().unwrap();
+
+            assert_eq!(
+                built.filter(),
+                Filter::Standard {
+                    filter: 0x560,
+                    mask: 0x7f0
+                }
+            );
+            assert_eq!(built.leaked_count(), 0);
+        }
+
+        #[test]
+        fn builder_splits_unrelated_ids_among_dual_filters() {
+            let built = FilterBuilder::new()
+                .id(0x100)
+                .range(0x560..=0x56f)
+                .build()
+                .unwrap();
+
+            assert!(matches!(built.filter(), Filter::Dual { .. }));
+            assert_eq!(built.leaked_count(), 0);
+            assert!(accepted(built.filter()).eq(core::iter::once(0x100).chain(0x560..=0x56f)));
+        }
+
+        #[test]
+        fn builder_reports_exactly_the_leaked_ids() {
+            let ids = [0x001, 0x002, 0x0f0, 0x400, 0x7ff];
+
+            let built = ids
+                .iter()
+                .fold(FilterBuilder::new(), |builder, id| builder.id(*id))
+                .build()
+                .unwrap();
+
+            for id in ids {
+                assert!(accepts(built.filter(), id, 0), "{:#x}", id);
+            }
+
+            assert!(accepted(built.filter())
+                .filter(|id| !ids.contains(id))
+                .eq(built.leaked()));
         }
     }
 }
 
-impl<TX: OutputPin, RX: InputPin> embedded_hal_0_2::blocking::can::Can for CanBus<TX, RX> {
-    type Frame = Frame;
-    type Error = Can02Error;
+/// A set of TWAI alert conditions
+///
+/// Only the conditions enabled with [`config::Config::alerts`] or [`CanBus::set_alerts`]
+/// are ever reported by the driver.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Alert(u32);
+
+impl Alert {
+    pub const NONE: Self = Self(TWAI_ALERT_NONE);
+    /// No more messages queued for transmission
+    pub const TX_IDLE: Self = Self(TWAI_ALERT_TX_IDLE);
+    /// The previous transmission was successful
+    pub const TX_SUCCESS: Self = Self(TWAI_ALERT_TX_SUCCESS);
+    /// A frame has been received and added to the RX queue
+    pub const RX_DATA: Self = Self(TWAI_ALERT_RX_DATA);
+    /// Both error counters have dropped below the error warning limit
+    pub const BELOW_ERR_WARN: Self = Self(TWAI_ALERT_BELOW_ERR_WARN);
+    /// The controller has become error active
+    pub const ERR_ACTIVE: Self = Self(TWAI_ALERT_ERR_ACTIVE);
+    /// Bus-off recovery is in progress
+    pub const RECOVERY_IN_PROGRESS: Self = Self(TWAI_ALERT_RECOVERY_IN_PROGRESS);
+    /// Bus-off recovery has completed; the controller is stopped
+    pub const BUS_RECOVERED: Self = Self(TWAI_ALERT_BUS_RECOVERED);
+    /// The previous transmission lost arbitration
+    pub const ARB_LOST: Self = Self(TWAI_ALERT_ARB_LOST);
+    /// One of the error counters has exceeded the error warning limit
+    pub const ABOVE_ERR_WARN: Self = Self(TWAI_ALERT_ABOVE_ERR_WARN);
+    /// A bit, stuff, CRC, form or ACK error has occurred on the bus
+    pub const BUS_ERROR: Self = Self(TWAI_ALERT_BUS_ERROR);
+    /// The previous transmission has failed (single shot transmissions only)
+    pub const TX_FAILED: Self = Self(TWAI_ALERT_TX_FAILED);
+    /// A received frame was lost because the RX queue was full
+    pub const RX_QUEUE_FULL: Self = Self(TWAI_ALERT_RX_QUEUE_FULL);
+    /// The controller has become error passive
+    pub const ERR_PASS: Self = Self(TWAI_ALERT_ERR_PASS);
+    /// The controller has gone bus-off; see [`CanBus::recover`]
+    pub const BUS_OFF: Self = Self(TWAI_ALERT_BUS_OFF);
+    pub const ALL: Self = Self(TWAI_ALERT_ALL);
+
+    /// Create a set from raw `TWAI_ALERT_*` bits, ignoring unknown ones
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        Self(bits & TWAI_ALERT_ALL)
+    }
 
-    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
-        self.transmit_internal(frame, portMAX_DELAY)
-            .map_err(Can02Error::other)
+    pub const fn bits(&self) -> u32 {
+        self.0
     }
 
-    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
-        self.receive_internal(portMAX_DELAY)
-            .map_err(Can02Error::other)
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
     }
-}
 
-impl<TX: OutputPin, RX: InputPin> embedded_hal::can::blocking::Can for CanBus<TX, RX> {
-    type Frame = Frame;
-    type Error = CanError;
+    /// Returns true if all alerts of `other` are in this set
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
 
-    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
-        self.transmit_internal(frame, portMAX_DELAY)
-            .map_err(CanError::other)
+    /// Returns true if any alert of `other` is in this set
+    pub const fn intersects(&self, other: Self) -> bool {
+        self.0 & other.0 != 0
     }
 
-    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
-        self.receive_internal(portMAX_DELAY)
-            .map_err(CanError::other)
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
     }
-}
 
-impl<TX: OutputPin, RX: InputPin> embedded_hal_0_2::can::nb::Can for CanBus<TX, RX> {
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Alert {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Alert {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for Alert {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl BitAndAssign for Alert {
+    fn bitand_assign(&mut self, other: Self) {
+        self.0 &= other.0;
+    }
+}
+
+impl Not for Alert {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::from_bits_truncate(!self.0)
+    }
+}
+
+impl From<Alert> for u32 {
+    fn from(alert: Alert) -> Self {
+        alert.0
+    }
+}
+
+/// State of the TWAI controller
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum State {
+    /// Neither transmitting nor receiving
+    Stopped,
+    /// Taking part in bus activity
+    Running,
+    /// Disconnected from the bus after too many transmit errors
+    BusOff,
+    /// Bus-off recovery in progress
+    Recovering,
+}
+
+impl From<twai_state_t> for State {
+    #[allow(non_upper_case_globals)]
+    fn from(state: twai_state_t) -> Self {
+        match state {
+            twai_state_t_TWAI_STATE_RUNNING => State::Running,
+            twai_state_t_TWAI_STATE_BUS_OFF => State::BusOff,
+            twai_state_t_TWAI_STATE_RECOVERING => State::Recovering,
+            _ => State::Stopped,
+        }
+    }
+}
+
+/// Snapshot of the state, queue depths and error counters of the TWAI driver
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Status {
+    pub state: State,
+    /// Number of messages queued for transmission or being transmitted
+    pub tx_queued: u32,
+    /// Number of received messages waiting in the RX queue
+    pub rx_queued: u32,
+    /// Transmit error counter (TEC)
+    pub tx_error_counter: u32,
+    /// Receive error counter (REC)
+    pub rx_error_counter: u32,
+    /// Number of failed transmissions
+    pub tx_failed_count: u32,
+    /// Number of messages lost because the RX queue was full
+    pub rx_missed_count: u32,
+    /// Number of times arbitration was lost while transmitting
+    pub arb_lost_count: u32,
+    /// Number of bus errors detected
+    pub bus_error_count: u32,
+}
+
+impl From<twai_status_info_t> for Status {
+    fn from(status: twai_status_info_t) -> Self {
+        Self {
+            state: status.state.into(),
+            tx_queued: status.msgs_to_tx,
+            rx_queued: status.msgs_to_rx,
+            tx_error_counter: status.tx_error_counter,
+            rx_error_counter: status.rx_error_counter,
+            tx_failed_count: status.tx_failed_count,
+            rx_missed_count: status.rx_missed_count,
+            arb_lost_count: status.arb_lost_count,
+            bus_error_count: status.bus_error_count,
+        }
+    }
+}
+
+/// CAN abstraction
+pub struct CanBus<
+    TX: OutputPin,
+    RX: InputPin,
+    // default pins to allow type inference
+    CLKOUT: OutputPin = crate::gpio::Gpio1<crate::gpio::Output>,
+    BUSOFF: OutputPin = crate::gpio::Gpio2<crate::gpio::Output>,
+> {
+    can: CAN,
+    tx: TX,
+    rx: RX,
+    clkout: Option<CLKOUT>,
+    bus_off: Option<BUSOFF>,
+    alert_task: TaskConfig,
+}
+
+unsafe impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin> Send
+    for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
+}
+
+impl<TX: OutputPin, RX: InputPin> CanBus<TX, RX> {
+    pub fn new(can: CAN, tx: TX, rx: RX, config: config::Config) -> Result<Self, EspError> {
+        Self::new_with_outputs(can, tx, rx, None, None, config)
+    }
+}
+
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin>
+    CanBus<TX, RX, CLKOUT, BUSOFF>
+{
+    /// Create a CAN bus which optionally also drives a divided APB clock on `clkout`
+    /// (see [`config::Config::clkout_divider`]) and signals the bus-off state on `bus_off`
+    pub fn new_with_outputs(
+        can: CAN,
+        tx: TX,
+        rx: RX,
+        clkout: Option<CLKOUT>,
+        bus_off: Option<BUSOFF>,
+        config: config::Config,
+    ) -> Result<Self, EspError> {
+        config.timing.validate()?;
+        config.intr_flags.validate()?;
+
+        if clkout.is_some()
+            && !(config.clkout_divider == 1
+                || (2..=14).contains(&config.clkout_divider) && config.clkout_divider % 2 == 0)
+        {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        let general_config = twai_general_config_t {
+            mode: config.mode.into(),
+            tx_io: tx.pin(),
+            rx_io: rx.pin(),
+            clkout_io: clkout.as_ref().map_or(-1, |pin| pin.pin()),
+            bus_off_io: bus_off.as_ref().map_or(-1, |pin| pin.pin()),
+            tx_queue_len: config.tx_queue_len,
+            rx_queue_len: config.rx_queue_len,
+            alerts_enabled: config.alerts.bits(),
+            clkout_divider: if clkout.is_some() {
+                config.clkout_divider
+            } else {
+                0
+            },
+            intr_flags: config.intr_flags.into(),
+        };
+
+        let timing_config = config.timing.into();
+
+        // modify filter and mask to be compatible with TWAI acceptance filter
+        let filter_config = config.filter.into();
+
+        esp!(unsafe { twai_driver_install(&general_config, &timing_config, &filter_config) })?;
+        esp!(unsafe { twai_start() })?;
+
+        ALERT_STATE
+            .user_alerts
+            .store(config.alerts.bits(), Ordering::SeqCst);
+
+        Ok(Self {
+            can,
+            tx,
+            rx,
+            clkout,
+            bus_off,
+            alert_task: config.alert_task,
+        })
+    }
+
+    pub fn release(self) -> Result<(CAN, TX, RX), EspError> {
+        let (can, tx, rx, _, _) = self.release_with_outputs()?;
+
+        Ok((can, tx, rx))
+    }
+
+    pub fn release_with_outputs(
+        self,
+    ) -> Result<(CAN, TX, RX, Option<CLKOUT>, Option<BUSOFF>), EspError> {
+        AlertState::stop();
+
+        esp!(unsafe { twai_stop() })?;
+        esp!(unsafe { twai_driver_uninstall() })?;
+
+        Ok((self.can, self.tx, self.rx, self.clkout, self.bus_off))
+    }
+
+    /// Current state, queue depths and error counters of the driver
+    pub fn status(&self) -> Result<Status, EspError> {
+        let mut status: twai_status_info_t = Default::default();
+
+        esp!(unsafe { twai_get_status_info(&mut status) })?;
+
+        Ok(status.into())
+    }
+
+    /// Change the set of conditions reported as alerts, returning the previous set
+    pub fn set_alerts(&mut self, alerts: Alert) -> Result<Alert, EspError> {
+        let driver_alerts = if ALERT_STATE.async_enabled.load(Ordering::SeqCst) {
+            alerts | ASYNC_ALERTS
+        } else {
+            alerts
+        };
+
+        esp!(unsafe { twai_reconfigure_alerts(driver_alerts.bits(), ptr::null_mut()) })?;
+
+        Ok(Alert(
+            ALERT_STATE
+                .user_alerts
+                .swap(alerts.bits(), Ordering::SeqCst),
+        ))
+    }
+
+    /// Wait for up to `timeout` (`None` waits forever) for alerts to be raised,
+    /// returning all alerts raised since the last call
+    ///
+    /// Returns an empty set if no alert was raised within `timeout`.
+    /// Fails with `ESP_ERR_INVALID_STATE` once alerts are delivered to a callback
+    /// or to async waiters, see [`CanBus::subscribe_alerts`] and [`CanBus::wait_alerts`].
+    pub fn read_alerts(&self, timeout: Option<Duration>) -> Result<Alert, EspError> {
+        if AlertState::enabled() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        let mut alerts = 0;
+
+        match unsafe {
+            twai_read_alerts(
+                &mut alerts,
+                timeout.map_or(portMAX_DELAY, |timeout| TickType::from(timeout).0),
+            )
+        } {
+            ESP_OK => Ok(Alert::from_bits_truncate(alerts)),
+            ESP_ERR_TIMEOUT => Ok(Alert::NONE),
+            err => Err(EspError::from(err).unwrap()),
+        }
+    }
+
+    /// Deliver alerts to `callback` from the alert task (see [`config::Config::alert_task`])
+    ///
+    /// Alerts are delivered until [`CanBus::unsubscribe_alerts`] is called, which must not
+    /// happen from within the callback. The alert task keeps reading the alerts from the
+    /// driver until the bus is released.
+    #[cfg(feature = "alloc")]
+    pub fn subscribe_alerts(
+        &mut self,
+        callback: impl FnMut(Alert) + Send + 'static,
+    ) -> Result<(), EspError> {
+        *ALERT_CALLBACK.lock() = Some(Box::new(callback));
+
+        AlertState::start(&self.alert_task)
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn unsubscribe_alerts(&mut self) {
+        *ALERT_CALLBACK.lock() = None;
+    }
+
+    /// Wait for alerts to be raised, returning all alerts raised since the last call
+    ///
+    /// The first call starts the alert task (see [`config::Config::alert_task`]), which
+    /// takes over reading the alerts from the driver until the bus is released.
+    pub async fn wait_alerts(&self) -> Result<Alert, EspError> {
+        AlertState::start(&self.alert_task)?;
+
+        loop {
+            let alerts = ALERT_STATE.pending.swap(0, Ordering::SeqCst);
+
+            if alerts != 0 {
+                return Ok(Alert(alerts));
+            }
+
+            if !AlertState::enabled() {
+                return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+            }
+
+            ALERT_STATE.notification.wait().await;
+        }
+    }
+
+    /// Recover from the bus-off state and restart the controller
+    ///
+    /// Initiates the recovery sequence (128 occurrences of 11 consecutive recessive bits),
+    /// waits for up to `timeout` (`None` waits forever) for it to complete and then starts
+    /// the controller again. Fails with `ESP_ERR_INVALID_STATE` if the controller is not
+    /// bus-off and with `ESP_ERR_TIMEOUT` if the recovery did not complete in time.
+    pub fn recover(&mut self, timeout: Option<Duration>) -> Result<(), EspError> {
+        if self.status()?.state != State::BusOff {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        esp!(unsafe { twai_initiate_recovery() })?;
+
+        let mut remaining = timeout.map(|timeout| TickType::from(timeout).0);
+
+        // The status is polled rather than waiting for `Alert::BUS_RECOVERED`,
+        // as that alert might not be enabled or might be consumed by another reader
+        while self.status()?.state != State::Stopped {
+            match remaining {
+                Some(0) => return Err(EspError::from(ESP_ERR_TIMEOUT as i32).unwrap()),
+                Some(ref mut ticks) => *ticks -= 1,
+                None => (),
+            }
+
+            unsafe { vTaskDelay(1) };
+        }
+
+        esp!(unsafe { twai_start() })
+    }
+
+    /// Split the bus into halves which can be used from different threads
+    ///
+    /// The driver stays installed. The transmitting half keeps the CAN peripheral and the
+    /// TX and clkout pins, the receiving half the RX and bus-off pins; [`CanBus::join`]
+    /// puts them back together.
+    pub fn split(self) -> (CanTx<TX, CLKOUT>, CanRx<RX, BUSOFF>) {
+        (
+            CanTx {
+                can: self.can,
+                tx: self.tx,
+                clkout: self.clkout,
+                alert_task: self.alert_task,
+            },
+            CanRx {
+                rx: self.rx,
+                bus_off: self.bus_off,
+                alert_task: self.alert_task,
+            },
+        )
+    }
+
+    /// Join the halves created by [`CanBus::split`]
+    pub fn join(tx: CanTx<TX, CLKOUT>, rx: CanRx<RX, BUSOFF>) -> Self {
+        Self {
+            can: tx.can,
+            tx: tx.tx,
+            rx: rx.rx,
+            clkout: tx.clkout,
+            bus_off: rx.bus_off,
+            alert_task: tx.alert_task,
+        }
+    }
+
+    /// Queue `frame` for transmission, waiting asynchronously while the TX queue is full
+    pub async fn transmit_async(&mut self, frame: &Frame) -> Result<(), EspError> {
+        transmit_async(frame, &self.alert_task).await
+    }
+
+    /// Receive a frame, waiting asynchronously until one is available
+    pub async fn receive_async(&mut self) -> Result<Frame, EspError> {
+        receive_async(&self.alert_task).await
+    }
+
+    fn transmit_internal(&mut self, frame: &Frame, delay: TickType_t) -> Result<(), EspError> {
+        transmit(frame, delay)
+    }
+
+    fn receive_internal(&mut self, delay: TickType_t) -> Result<Frame, EspError> {
+        receive(delay)
+    }
+}
+
+/// Transmitting half of a [`CanBus`]
+///
+/// Implements the embedded-hal `Can` traits so that it can be used with generic code.
+/// As those traits also cover receiving, [`embedded_hal::can::nb::Can::receive`] and its
+/// siblings always fail with `ESP_ERR_NOT_SUPPORTED`; use [`CanRx`] for receiving.
+pub struct CanTx<TX: OutputPin, CLKOUT: OutputPin = crate::gpio::Gpio1<crate::gpio::Output>> {
+    can: CAN,
+    tx: TX,
+    clkout: Option<CLKOUT>,
+    alert_task: TaskConfig,
+}
+
+unsafe impl<TX: OutputPin, CLKOUT: OutputPin> Send for CanTx<TX, CLKOUT> {}
+
+impl<TX: OutputPin, CLKOUT: OutputPin> CanTx<TX, CLKOUT> {
+    /// Queue `frame` for transmission, waiting for up to `timeout` (`None` waits forever)
+    /// while the TX queue is full
+    pub fn transmit_timeout(
+        &mut self,
+        frame: &Frame,
+        timeout: Option<Duration>,
+    ) -> Result<(), EspError> {
+        transmit(
+            frame,
+            timeout.map_or(portMAX_DELAY, |timeout| TickType::from(timeout).0),
+        )
+    }
+
+    /// Queue `frame` for transmission, waiting asynchronously while the TX queue is full
+    pub async fn transmit_async(&mut self, frame: &Frame) -> Result<(), EspError> {
+        transmit_async(frame, &self.alert_task).await
+    }
+}
+
+/// Receiving half of a [`CanBus`]
+///
+/// Implements the embedded-hal `Can` traits so that it can be used with generic code.
+/// As those traits also cover transmitting, [`embedded_hal::can::nb::Can::transmit`] and
+/// its siblings always fail with `ESP_ERR_NOT_SUPPORTED`; use [`CanTx`] for transmitting.
+pub struct CanRx<RX: InputPin, BUSOFF: OutputPin = crate::gpio::Gpio2<crate::gpio::Output>> {
+    rx: RX,
+    bus_off: Option<BUSOFF>,
+    alert_task: TaskConfig,
+}
+
+unsafe impl<RX: InputPin, BUSOFF: OutputPin> Send for CanRx<RX, BUSOFF> {}
+
+impl<RX: InputPin, BUSOFF: OutputPin> CanRx<RX, BUSOFF> {
+    /// Receive a frame, waiting for up to `timeout` (`None` waits forever)
+    pub fn receive_timeout(&mut self, timeout: Option<Duration>) -> Result<Frame, EspError> {
+        receive(timeout.map_or(portMAX_DELAY, |timeout| TickType::from(timeout).0))
+    }
+
+    /// Receive a frame, waiting asynchronously until one is available
+    pub async fn receive_async(&mut self) -> Result<Frame, EspError> {
+        receive_async(&self.alert_task).await
+    }
+}
+
+/// The error of the direction a half of a split [`CanBus`] does not handle
+fn not_supported() -> EspError {
+    EspError::from(ESP_ERR_NOT_SUPPORTED as i32).unwrap()
+}
+
+fn nb_transmit<E>(frame: &Frame, error: fn(EspError) -> E) -> nb::Result<Option<Frame>, E> {
+    match transmit(frame, 0) {
+        Ok(_) => Ok(None),
+        Err(e) if e.code() == ESP_FAIL || e.code() == ESP_ERR_TIMEOUT as i32 => {
+            Err(nb::Error::WouldBlock)
+        }
+        Err(e) => Err(nb::Error::Other(error(e))),
+    }
+}
+
+fn nb_receive<E>(error: fn(EspError) -> E) -> nb::Result<Frame, E> {
+    match receive(0) {
+        Ok(frame) => Ok(frame),
+        Err(e) if e.code() == ESP_ERR_TIMEOUT as i32 => Err(nb::Error::WouldBlock),
+        Err(e) => Err(nb::Error::Other(error(e))),
+    }
+}
+
+impl<TX: OutputPin, CLKOUT: OutputPin> embedded_hal_0_2::blocking::can::Can for CanTx<TX, CLKOUT> {
+    type Frame = Frame;
+    type Error = Can02Error;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        transmit(frame, portMAX_DELAY).map_err(Can02Error::other)
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        Err(Can02Error::other(not_supported()))
+    }
+}
+
+impl<TX: OutputPin, CLKOUT: OutputPin> embedded_hal::can::blocking::Can for CanTx<TX, CLKOUT> {
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        transmit(frame, portMAX_DELAY).map_err(CanError::other)
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        Err(CanError::other(not_supported()))
+    }
+}
+
+impl<TX: OutputPin, CLKOUT: OutputPin> embedded_hal_0_2::can::nb::Can for CanTx<TX, CLKOUT> {
+    type Frame = Frame;
+    type Error = Can02Error;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        nb_transmit(frame, Can02Error::other)
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        Err(nb::Error::Other(Can02Error::other(not_supported())))
+    }
+}
+
+impl<TX: OutputPin, CLKOUT: OutputPin> embedded_hal::can::nb::Can for CanTx<TX, CLKOUT> {
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        nb_transmit(frame, CanError::other)
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        Err(nb::Error::Other(CanError::other(not_supported())))
+    }
+}
+
+impl<RX: InputPin, BUSOFF: OutputPin> embedded_hal_0_2::blocking::can::Can for CanRx<RX, BUSOFF> {
+    type Frame = Frame;
+    type Error = Can02Error;
+
+    fn transmit(&mut self, _frame: &Self::Frame) -> Result<(), Self::Error> {
+        Err(Can02Error::other(not_supported()))
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        receive(portMAX_DELAY).map_err(Can02Error::other)
+    }
+}
+
+impl<RX: InputPin, BUSOFF: OutputPin> embedded_hal::can::blocking::Can for CanRx<RX, BUSOFF> {
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, _frame: &Self::Frame) -> Result<(), Self::Error> {
+        Err(CanError::other(not_supported()))
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        receive(portMAX_DELAY).map_err(CanError::other)
+    }
+}
+
+impl<RX: InputPin, BUSOFF: OutputPin> embedded_hal_0_2::can::nb::Can for CanRx<RX, BUSOFF> {
+    type Frame = Frame;
+    type Error = Can02Error;
+
+    fn transmit(&mut self, _frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        Err(nb::Error::Other(Can02Error::other(not_supported())))
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        nb_receive(Can02Error::other)
+    }
+}
+
+impl<RX: InputPin, BUSOFF: OutputPin> embedded_hal::can::nb::Can for CanRx<RX, BUSOFF> {
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, _frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        Err(nb::Error::Other(CanError::other(not_supported())))
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        nb_receive(CanError::other)
+    }
+}
+
+fn transmit(frame: &Frame, delay: TickType_t) -> Result<(), EspError> {
+    esp!(unsafe { twai_transmit(&frame.0, delay) })
+}
+
+fn receive(delay: TickType_t) -> Result<Frame, EspError> {
+    let mut rx_msg = twai_message_t {
+        ..Default::default()
+    };
+
+    match esp_result!(unsafe { twai_receive(&mut rx_msg, delay) }, ()) {
+        Ok(_) => Ok(Frame(rx_msg)),
+        Err(err) => Err(err),
+    }
+}
+
+async fn transmit_async(frame: &Frame, alert_task: &TaskConfig) -> Result<(), EspError> {
+    let state = AlertState::start_async(alert_task)?;
+
+    loop {
+        match transmit(frame, 0) {
+            Err(e) if e.code() == ESP_FAIL || e.code() == ESP_ERR_TIMEOUT as i32 => (),
+            other => return other,
+        }
+
+        state.tx.wait().await;
+
+        if !AlertState::enabled() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+    }
+}
+
+async fn receive_async(alert_task: &TaskConfig) -> Result<Frame, EspError> {
+    let state = AlertState::start_async(alert_task)?;
+
+    loop {
+        match receive(0) {
+            Err(e) if e.code() == ESP_ERR_TIMEOUT as i32 => (),
+            other => return other,
+        }
+
+        state.rx.wait().await;
+
+        if !AlertState::enabled() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+    }
+}
+
+struct AlertState {
+    pump: Pump,
+    pending: AtomicU32,
+    /// Alerts requested by the user, as opposed to those needed by the async senders and receivers
+    user_alerts: AtomicU32,
+    async_enabled: AtomicBool,
+    notification: HalIsrNotification,
+    rx: HalIsrNotification,
+    tx: HalIsrNotification,
+}
+
+static ALERT_STATE: AlertState = AlertState::new();
+
+#[cfg(feature = "alloc")]
+#[allow(clippy::type_complexity)]
+static ALERT_CALLBACK: crate::mutex::Mutex<Option<Box<dyn FnMut(Alert) + Send>>> =
+    crate::mutex::Mutex::new(None);
+
+impl AlertState {
+    const fn new() -> Self {
+        Self {
+            pump: Pump::new(),
+            pending: AtomicU32::new(0),
+            user_alerts: AtomicU32::new(0),
+            async_enabled: AtomicBool::new(false),
+            notification: HalIsrNotification::new(),
+            rx: HalIsrNotification::new(),
+            tx: HalIsrNotification::new(),
+        }
+    }
+
+    fn enabled() -> bool {
+        ALERT_STATE.pump.is_running()
+    }
+
+    /// Start the task reading the alerts from the driver, unless it is running already
+    fn start(alert_task: &TaskConfig) -> Result<(), EspError> {
+        let state = &ALERT_STATE;
+
+        if state.pump.is_running() {
+            return Ok(());
+        }
+
+        state.pending.store(0, Ordering::SeqCst);
+        state.notification.reset();
+        state.rx.reset();
+        state.tx.reset();
+
+        state.pump.start(alert_task, Self::forward)?;
+
+        Ok(())
+    }
+
+    /// Start the alert task if needed and make the driver raise the alerts it
+    /// uses to wake up async senders and receivers
+    fn start_async(alert_task: &TaskConfig) -> Result<&'static Self, EspError> {
+        let state = &ALERT_STATE;
+
+        Self::start(alert_task)?;
+
+        if !state.async_enabled.swap(true, Ordering::SeqCst) {
+            let alerts = Alert(state.user_alerts.load(Ordering::SeqCst)) | ASYNC_ALERTS;
+
+            if let Err(e) = esp!(unsafe { twai_reconfigure_alerts(alerts.bits(), ptr::null_mut()) })
+            {
+                state.async_enabled.store(false, Ordering::SeqCst);
+
+                return Err(e);
+            }
+        }
+
+        Ok(state)
+    }
+
+    fn stop() {
+        let state = &ALERT_STATE;
+
+        if state.pump.stop() {
+            state.async_enabled.store(false, Ordering::SeqCst);
+
+            // Pending waits fail with `ESP_ERR_INVALID_STATE` once woken up
+            state.notification.notify();
+            state.rx.notify();
+            state.tx.notify();
+        }
+
+        #[cfg(feature = "alloc")]
+        {
+            *ALERT_CALLBACK.lock() = None;
+        }
+    }
+
+    /// Forwards the alerts raised by the driver to the subscribed callback and async waiters
+    fn forward() {
+        let state = &ALERT_STATE;
+
+        let mut alerts = 0;
+
+        if unsafe { twai_read_alerts(&mut alerts, ALERT_TASK_POLL_TICKS) } != ESP_OK {
+            return;
+        }
+
+        let alerts = Alert::from_bits_truncate(alerts);
+
+        if alerts.contains(Alert::RX_DATA) {
+            state.rx.notify();
+        }
+
+        if alerts.intersects(ASYNC_ALERTS & !Alert::RX_DATA) {
+            state.tx.notify();
+        }
+
+        // Only report the alerts the user asked for
+        let alerts = alerts & Alert(state.user_alerts.load(Ordering::SeqCst));
+
+        if alerts.is_empty() {
+            return;
+        }
+
+        state.pending.fetch_or(alerts.bits(), Ordering::SeqCst);
+
+        #[cfg(feature = "alloc")]
+        if let Some(callback) = ALERT_CALLBACK.lock().as_mut() {
+            callback(alerts);
+        }
+
+        state.notification.notify();
+    }
+}
+
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin>
+    embedded_hal_0_2::blocking::can::Can for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
+    type Frame = Frame;
+    type Error = Can02Error;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        self.transmit_internal(frame, portMAX_DELAY)
+            .map_err(Can02Error::other)
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        self.receive_internal(portMAX_DELAY)
+            .map_err(Can02Error::other)
+    }
+}
+
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin>
+    embedded_hal::can::blocking::Can for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
+    type Frame = Frame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        self.transmit_internal(frame, portMAX_DELAY)
+            .map_err(CanError::other)
+    }
+
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        self.receive_internal(portMAX_DELAY)
+            .map_err(CanError::other)
+    }
+}
+
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin>
+    embedded_hal_0_2::can::nb::Can for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
     type Frame = Frame;
     type Error = Can02Error;
 
@@ -354,7 +1923,9 @@ impl<TX: OutputPin, RX: InputPin> embedded_hal_0_2::can::nb::Can for CanBus<TX,
     }
 }
 
-impl<TX: OutputPin, RX: InputPin> embedded_hal::can::nb::Can for CanBus<TX, RX> {
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin> embedded_hal::can::nb::Can
+    for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
     type Frame = Frame;
     type Error = CanError;
 
@@ -376,6 +1947,20 @@ impl<TX: OutputPin, RX: InputPin> embedded_hal::can::nb::Can for CanBus<TX, RX>
     }
 }
 
+impl<TX: OutputPin, RX: InputPin, CLKOUT: OutputPin, BUSOFF: OutputPin> isotp::ReceiveTimeout
+    for CanBus<TX, RX, CLKOUT, BUSOFF>
+{
+    fn receive_timeout(&mut self, timeout: Option<Duration>) -> nb::Result<Frame, CanError> {
+        match self
+            .receive_internal(timeout.map_or(portMAX_DELAY, |timeout| TickType::from(timeout).0))
+        {
+            Ok(frame) => Ok(frame),
+            Err(e) if e.code() == ESP_ERR_TIMEOUT as i32 => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(CanError::other(e))),
+        }
+    }
+}
+
 pub struct Frame(twai_message_t);
 
 impl Frame {
@@ -0,0 +1,963 @@
+//! ISO-TP (ISO 15765-2) transport protocol on top of a CAN bus.
+//!
+//! Segments payloads of up to 4095 bytes into single, first and consecutive frames,
+//! and takes care of the flow control handshake with the peer.
+//! Normal addressing with classic (8 byte) CAN frames is used.
+//!
+//! The transport works with any [`embedded_hal::can::nb::Can`] which implements
+//! [`ReceiveTimeout`], so it can be exercised on the host with a mock bus and a mock [`Timer`].
+//! While waiting for a frame, the transport blocks in the driver until the frame arrives or the
+//! N_Bs or N_Cr timeout elapses. Consecutive frames sent back-to-back by the peer are therefore
+//! taken off the bus as they arrive, instead of overflowing the small RX queue of the driver
+//! while the task sleeps.
+//!
+//! # Example
+//!
+//! ```
+//! use embedded_hal::can::StandardId;
+//! use esp_idf_hal::can::isotp;
+//!
+//! let config = isotp::config::Config::new(
+//!     StandardId::new(0x7e0).unwrap().into(),
+//!     StandardId::new(0x7e8).unwrap().into(),
+//! )
+//! .padding(Some(0xcc));
+//!
+//! let mut isotp = isotp::IsoTp::new(can, isotp::SystemTimer, config);
+//!
+//! isotp.send(&[0x22, 0xf1, 0x90])?;
+//!
+//! let mut response = [0; 4095];
+//! let len = isotp.receive(&mut response)?;
+//! ```
+
+use core::time::Duration;
+
+use embedded_hal::can::nb::Can;
+use embedded_hal::can::Frame;
+
+/// Largest payload which can be transferred
+pub const MAX_PAYLOAD_LEN: usize = 4095;
+
+const FRAME_LEN: usize = 8;
+
+const SINGLE_FRAME: u8 = 0x00;
+const FIRST_FRAME: u8 = 0x10;
+const CONSECUTIVE_FRAME: u8 = 0x20;
+const FLOW_CONTROL: u8 = 0x30;
+
+const SINGLE_FRAME_MAX_DATA: usize = FRAME_LEN - 1;
+const FIRST_FRAME_DATA: usize = FRAME_LEN - 2;
+const CONSECUTIVE_FRAME_DATA: usize = FRAME_LEN - 1;
+
+pub mod config {
+    use core::time::Duration;
+
+    use embedded_hal::can::Id;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct Config {
+        /// ID of the frames sent to the peer
+        pub tx_id: Id,
+        /// ID of the frames received from the peer
+        pub rx_id: Id,
+        /// Number of consecutive frames the peer may send before waiting for the
+        /// next flow control frame; `0` lets it send all of them at once
+        pub block_size: u8,
+        /// Minimum gap the peer should leave between consecutive frames
+        pub st_min: Duration,
+        /// If set, frames are padded to 8 bytes with this value
+        pub padding: Option<u8>,
+        /// How long to wait for a flow control frame from the peer (N_Bs)
+        pub flow_control_timeout: Duration,
+        /// How long to wait for the next consecutive frame from the peer (N_Cr)
+        pub consecutive_frame_timeout: Duration,
+        /// Number of "wait" flow control frames accepted before giving up
+        pub max_wait_frames: u8,
+        /// How long to sleep before retrying when the transmit queue is full
+        pub poll_interval: Duration,
+    }
+
+    impl Config {
+        pub fn new(tx_id: Id, rx_id: Id) -> Self {
+            Self {
+                tx_id,
+                rx_id,
+                block_size: 0,
+                st_min: Duration::from_millis(0),
+                padding: None,
+                flow_control_timeout: Duration::from_millis(1000),
+                consecutive_frame_timeout: Duration::from_millis(1000),
+                max_wait_frames: 10,
+                poll_interval: Duration::from_millis(10),
+            }
+        }
+
+        #[must_use]
+        pub fn block_size(mut self, block_size: u8) -> Self {
+            self.block_size = block_size;
+            self
+        }
+
+        /// Values above 127ms are sent as 127ms, and sub-millisecond values are
+        /// rounded to 100us steps
+        #[must_use]
+        pub fn st_min(mut self, st_min: Duration) -> Self {
+            self.st_min = st_min;
+            self
+        }
+
+        #[must_use]
+        pub fn padding(mut self, padding: Option<u8>) -> Self {
+            self.padding = padding;
+            self
+        }
+
+        #[must_use]
+        pub fn flow_control_timeout(mut self, timeout: Duration) -> Self {
+            self.flow_control_timeout = timeout;
+            self
+        }
+
+        #[must_use]
+        pub fn consecutive_frame_timeout(mut self, timeout: Duration) -> Self {
+            self.consecutive_frame_timeout = timeout;
+            self
+        }
+
+        #[must_use]
+        pub fn max_wait_frames(mut self, max_wait_frames: u8) -> Self {
+            self.max_wait_frames = max_wait_frames;
+            self
+        }
+
+        #[must_use]
+        pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+            self.poll_interval = poll_interval;
+            self
+        }
+    }
+}
+
+/// A bus which can block until a frame is received
+pub trait ReceiveTimeout: Can {
+    /// Wait until a frame is received, or fail with `WouldBlock` once `timeout` elapses.
+    /// `None` waits forever
+    fn receive_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> nb::Result<Self::Frame, Self::Error>;
+}
+
+/// Source of time for the protocol timeouts and the gaps between consecutive frames
+pub trait Timer {
+    /// Monotonic time since an arbitrary origin
+    fn now(&mut self) -> Duration;
+
+    fn delay(&mut self, duration: Duration);
+}
+
+/// [`Timer`] based on the `esp_timer` service and the FreeRTOS scheduler
+pub struct SystemTimer;
+
+impl Timer for SystemTimer {
+    fn now(&mut self) -> Duration {
+        crate::esp_timer::now()
+    }
+
+    fn delay(&mut self, duration: Duration) {
+        // Gaps shorter than a tick are busy-waited
+        if duration >= Duration::from(crate::delay::TickType(1)) {
+            unsafe { esp_idf_sys::vTaskDelay(crate::delay::TickType::from(duration).0) };
+        } else {
+            embedded_hal_0_2::blocking::delay::DelayUs::delay_us(
+                &mut crate::delay::Ets,
+                duration.as_micros() as u32,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IsoTpError<E> {
+    /// Error of the underlying CAN bus
+    Can(E),
+    /// The peer did not send the expected flow control or consecutive frame in time
+    Timeout,
+    /// The payload does not fit in the receive buffer, or the peer reported
+    /// that it does not fit in its buffer
+    Overflow,
+    /// The payload is larger than [`MAX_PAYLOAD_LEN`]
+    TooLong,
+    /// A consecutive frame was lost or received out of order
+    WrongSequenceNumber,
+    /// The peer sent a frame which is not valid at this point of the transfer
+    UnexpectedFrame,
+    /// The peer sent more "wait" flow control frames than allowed
+    WaitLimitExceeded,
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for IsoTpError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: core::fmt::Debug> std::error::Error for IsoTpError<E> {}
+
+/// Flow status of a flow control frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum FlowStatus {
+    ContinueToSend,
+    Wait,
+    Overflow,
+}
+
+/// Protocol control information of a received frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Pci<'a> {
+    Single(&'a [u8]),
+    First {
+        len: usize,
+        data: &'a [u8],
+    },
+    Consecutive {
+        sequence_number: u8,
+        data: &'a [u8],
+    },
+    FlowControl {
+        status: FlowStatus,
+        block_size: u8,
+        st_min: Duration,
+    },
+}
+
+impl<'a> Pci<'a> {
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        let pci = *data.first()?;
+
+        match pci & 0xf0 {
+            SINGLE_FRAME => {
+                let len = (pci & 0x0f) as usize;
+
+                (1..=SINGLE_FRAME_MAX_DATA)
+                    .contains(&len)
+                    .then(|| data.get(1..1 + len))
+                    .flatten()
+                    .map(Pci::Single)
+            }
+            FIRST_FRAME => {
+                let len = ((pci & 0x0f) as usize) << 8 | *data.get(1)? as usize;
+
+                (len > SINGLE_FRAME_MAX_DATA && data.len() == FRAME_LEN).then(|| Pci::First {
+                    len,
+                    data: &data[2..],
+                })
+            }
+            CONSECUTIVE_FRAME => Some(Pci::Consecutive {
+                sequence_number: pci & 0x0f,
+                data: &data[1..],
+            }),
+            FLOW_CONTROL => {
+                let status = match pci & 0x0f {
+                    0 => FlowStatus::ContinueToSend,
+                    1 => FlowStatus::Wait,
+                    2 => FlowStatus::Overflow,
+                    _ => return None,
+                };
+
+                Some(Pci::FlowControl {
+                    status,
+                    block_size: *data.get(1)?,
+                    st_min: decode_st_min(*data.get(2)?),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn decode_st_min(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7f => Duration::from_millis(st_min as u64),
+        0xf1..=0xf9 => Duration::from_micros((st_min - 0xf0) as u64 * 100),
+        // Reserved values are to be treated as the maximum of 127ms
+        _ => Duration::from_millis(0x7f),
+    }
+}
+
+fn encode_st_min(st_min: Duration) -> u8 {
+    if st_min >= Duration::from_millis(1) {
+        core::cmp::min(st_min.as_millis(), 0x7f) as u8
+    } else if st_min >= Duration::from_micros(100) {
+        0xf0 + (st_min.as_micros() / 100) as u8
+    } else {
+        0
+    }
+}
+
+/// ISO-TP transport over a CAN bus
+pub struct IsoTp<C, T> {
+    can: C,
+    timer: T,
+    config: config::Config,
+}
+
+impl<C, T> IsoTp<C, T>
+where
+    C: ReceiveTimeout,
+    T: Timer,
+{
+    pub fn new(can: C, timer: T, config: config::Config) -> Self {
+        Self { can, timer, config }
+    }
+
+    pub fn release(self) -> (C, T) {
+        (self.can, self.timer)
+    }
+
+    /// Send `payload` to the peer, waiting for its flow control frames as needed
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), IsoTpError<C::Error>> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(IsoTpError::TooLong);
+        }
+
+        if payload.len() <= SINGLE_FRAME_MAX_DATA {
+            return self.transmit(&[SINGLE_FRAME | payload.len() as u8], payload);
+        }
+
+        let len = payload.len();
+        let (first, mut rest) = payload.split_at(FIRST_FRAME_DATA);
+
+        self.transmit(&[FIRST_FRAME | (len >> 8) as u8, len as u8], first)?;
+
+        let mut sequence_number = 1;
+
+        while !rest.is_empty() {
+            let (block_size, st_min) = self.wait_flow_control()?;
+
+            let mut sent = 0;
+
+            while !rest.is_empty() && (block_size == 0 || sent < block_size) {
+                if sent > 0 {
+                    self.timer.delay(st_min);
+                }
+
+                let (data, remaining) =
+                    rest.split_at(core::cmp::min(rest.len(), CONSECUTIVE_FRAME_DATA));
+
+                self.transmit(&[CONSECUTIVE_FRAME | sequence_number], data)?;
+
+                rest = remaining;
+                sequence_number = (sequence_number + 1) & 0x0f;
+                sent += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receive a payload from the peer into `buf`, returning its length
+    ///
+    /// Waits indefinitely for the first frame of the payload.
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<usize, IsoTpError<C::Error>> {
+        let frame = self.receive_frame(None)?;
+
+        let (len, data) = match Pci::decode(frame.data()) {
+            Some(Pci::Single(data)) => {
+                return buf
+                    .get_mut(..data.len())
+                    .map(|buf| {
+                        buf.copy_from_slice(data);
+                        data.len()
+                    })
+                    .ok_or(IsoTpError::Overflow)
+            }
+            Some(Pci::First { len, data }) => (len, data),
+            _ => return Err(IsoTpError::UnexpectedFrame),
+        };
+
+        if len > buf.len() {
+            self.send_flow_control(FlowStatus::Overflow)?;
+
+            return Err(IsoTpError::Overflow);
+        }
+
+        buf[..data.len()].copy_from_slice(data);
+
+        let mut offset = data.len();
+        let mut sequence_number = 1;
+        let mut block_received = None;
+
+        while offset < len {
+            let block_size = self.config.block_size as usize;
+
+            // With a block size of 0 the peer sends all consecutive frames after the first flow control
+            if block_received.map_or(true, |received| block_size > 0 && received == block_size) {
+                self.send_flow_control(FlowStatus::ContinueToSend)?;
+
+                block_received = Some(0);
+            }
+
+            let timeout = self.config.consecutive_frame_timeout;
+            let frame = self.receive_frame(Some(timeout))?;
+
+            match Pci::decode(frame.data()) {
+                Some(Pci::Consecutive {
+                    sequence_number: received,
+                    data,
+                }) => {
+                    if received != sequence_number {
+                        return Err(IsoTpError::WrongSequenceNumber);
+                    }
+
+                    let chunk = core::cmp::min(data.len(), len - offset);
+
+                    buf[offset..offset + chunk].copy_from_slice(&data[..chunk]);
+
+                    offset += chunk;
+                    sequence_number = (sequence_number + 1) & 0x0f;
+                }
+                _ => return Err(IsoTpError::UnexpectedFrame),
+            }
+
+            block_received = block_received.map(|received| received + 1);
+        }
+
+        Ok(len)
+    }
+
+    /// Wait for a "continue to send" flow control frame, returning the block size and STmin
+    fn wait_flow_control(&mut self) -> Result<(u8, Duration), IsoTpError<C::Error>> {
+        let mut waits = 0;
+
+        loop {
+            let timeout = self.config.flow_control_timeout;
+            let frame = self.receive_frame(Some(timeout))?;
+
+            match Pci::decode(frame.data()) {
+                Some(Pci::FlowControl {
+                    status: FlowStatus::ContinueToSend,
+                    block_size,
+                    st_min,
+                }) => return Ok((block_size, st_min)),
+                Some(Pci::FlowControl {
+                    status: FlowStatus::Wait,
+                    ..
+                }) => {
+                    waits += 1;
+
+                    if waits > self.config.max_wait_frames {
+                        return Err(IsoTpError::WaitLimitExceeded);
+                    }
+                }
+                Some(Pci::FlowControl {
+                    status: FlowStatus::Overflow,
+                    ..
+                }) => return Err(IsoTpError::Overflow),
+                _ => return Err(IsoTpError::UnexpectedFrame),
+            }
+        }
+    }
+
+    fn send_flow_control(&mut self, status: FlowStatus) -> Result<(), IsoTpError<C::Error>> {
+        let status = match status {
+            FlowStatus::ContinueToSend => 0,
+            FlowStatus::Wait => 1,
+            FlowStatus::Overflow => 2,
+        };
+
+        self.transmit(
+            &[
+                FLOW_CONTROL | status,
+                self.config.block_size,
+                encode_st_min(self.config.st_min),
+            ],
+            &[],
+        )
+    }
+
+    /// Receive the next frame with the RX ID within `timeout`, skipping frames with other IDs
+    fn receive_frame(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<C::Frame, IsoTpError<C::Error>> {
+        let deadline = timeout.map(|timeout| self.timer.now() + timeout);
+
+        loop {
+            let timeout = deadline.map(|deadline| deadline.saturating_sub(self.timer.now()));
+
+            match self.can.receive_timeout(timeout) {
+                Ok(frame) if frame.id() == self.config.rx_id && frame.is_data_frame() => {
+                    return Ok(frame)
+                }
+                Ok(_) | Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(e)) => return Err(IsoTpError::Can(e)),
+            }
+
+            if deadline.map_or(false, |deadline| self.timer.now() >= deadline) {
+                return Err(IsoTpError::Timeout);
+            }
+        }
+    }
+
+    fn transmit(&mut self, pci: &[u8], data: &[u8]) -> Result<(), IsoTpError<C::Error>> {
+        let mut payload = [self.config.padding.unwrap_or(0); FRAME_LEN];
+
+        let len = pci.len() + data.len();
+
+        payload[..pci.len()].copy_from_slice(pci);
+        payload[pci.len()..len].copy_from_slice(data);
+
+        let len = if self.config.padding.is_some() {
+            FRAME_LEN
+        } else {
+            len
+        };
+
+        let frame = C::Frame::new(self.config.tx_id, &payload[..len])
+            .expect("ISO-TP frames never exceed 8 bytes");
+
+        loop {
+            match self.can.transmit(&frame) {
+                Ok(_) => return Ok(()),
+                Err(nb::Error::WouldBlock) => self.timer.delay(self.config.poll_interval),
+                Err(nb::Error::Other(e)) => return Err(IsoTpError::Can(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use embedded_hal::can::{ErrorKind, Id, StandardId};
+
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    struct MockFrame {
+        id: Id,
+        remote: bool,
+        data: [u8; FRAME_LEN],
+        dlc: usize,
+    }
+
+    impl Frame for MockFrame {
+        fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+            let mut frame = Self::new_remote(id, data.len())?;
+
+            frame.remote = false;
+            frame.data[..data.len()].copy_from_slice(data);
+
+            Some(frame)
+        }
+
+        fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+            (dlc <= FRAME_LEN).then(|| Self {
+                id: id.into(),
+                remote: false,
+                data: [0; FRAME_LEN],
+                dlc,
+            })
+        }
+
+        fn is_extended(&self) -> bool {
+            matches!(self.id, Id::Extended(_))
+        }
+
+        fn is_remote_frame(&self) -> bool {
+            self.remote
+        }
+
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn dlc(&self) -> usize {
+            self.dlc
+        }
+
+        fn data(&self) -> &[u8] {
+            &self.data[..self.dlc]
+        }
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    struct MockError;
+
+    impl embedded_hal::can::Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    /// A bus on which the peer's frames arrive at scripted times, into an RX queue
+    /// which drops them when it is full, like the one of the TWAI driver
+    struct MockCan {
+        now: Rc<Cell<Duration>>,
+        rx: VecDeque<(Duration, MockFrame)>,
+        rx_queue: VecDeque<MockFrame>,
+        rx_queue_len: usize,
+        tx: Vec<MockFrame>,
+    }
+
+    impl MockCan {
+        /// Queue the frames which have arrived by now
+        fn deliver(&mut self) {
+            while matches!(self.rx.front(), Some((at, _)) if *at <= self.now.get()) {
+                let (_, frame) = self.rx.pop_front().unwrap();
+
+                if self.rx_queue.len() < self.rx_queue_len {
+                    self.rx_queue.push_back(frame);
+                }
+            }
+        }
+    }
+
+    impl Can for MockCan {
+        type Frame = MockFrame;
+        type Error = MockError;
+
+        fn transmit(&mut self, frame: &MockFrame) -> nb::Result<Option<MockFrame>, MockError> {
+            self.tx.push(*frame);
+
+            Ok(None)
+        }
+
+        fn receive(&mut self) -> nb::Result<MockFrame, MockError> {
+            self.deliver();
+
+            self.rx_queue.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl ReceiveTimeout for MockCan {
+        fn receive_timeout(
+            &mut self,
+            timeout: Option<Duration>,
+        ) -> nb::Result<MockFrame, MockError> {
+            self.deliver();
+
+            if self.rx_queue.is_empty() {
+                let deadline = timeout.map(|timeout| self.now.get() + timeout);
+
+                match self.rx.front() {
+                    Some((at, _)) if deadline.map_or(true, |deadline| *at <= deadline) => {
+                        self.now.set(*at);
+                        self.deliver();
+                    }
+                    _ => self.now.set(deadline.expect("Waiting forever for a frame")),
+                }
+            }
+
+            self.rx_queue.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    /// A clock which only advances when delaying
+    struct MockTimer {
+        now: Rc<Cell<Duration>>,
+        delays: Vec<Duration>,
+    }
+
+    impl Timer for MockTimer {
+        fn now(&mut self) -> Duration {
+            self.now.get()
+        }
+
+        fn delay(&mut self, duration: Duration) {
+            self.delays.push(duration);
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    const TX_ID: u16 = 0x7e0;
+    const RX_ID: u16 = 0x7e8;
+
+    fn id(id: u16) -> Id {
+        StandardId::new(id).unwrap().into()
+    }
+
+    fn frame(id_: u16, data: &[u8]) -> MockFrame {
+        MockFrame::new(id(id_), data).unwrap()
+    }
+
+    fn config() -> config::Config {
+        config::Config::new(id(TX_ID), id(RX_ID))
+    }
+
+    fn transport(config: config::Config, rx: &[(u64, MockFrame)]) -> IsoTp<MockCan, MockTimer> {
+        let now = Rc::new(Cell::new(Duration::from_millis(0)));
+
+        let can = MockCan {
+            now: now.clone(),
+            rx: rx
+                .iter()
+                .map(|(at, frame)| (Duration::from_millis(*at), *frame))
+                .collect(),
+            rx_queue: VecDeque::new(),
+            rx_queue_len: 5,
+            tx: Vec::new(),
+        };
+
+        let timer = MockTimer {
+            now,
+            delays: Vec::new(),
+        };
+
+        IsoTp::new(can, timer, config)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn sent(isotp: &IsoTp<MockCan, MockTimer>) -> Vec<Vec<u8>> {
+        isotp
+            .can
+            .tx
+            .iter()
+            .map(|frame| frame.data().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn sends_single_frame_with_padding() {
+        let mut isotp = transport(config().padding(Some(0xcc)), &[]);
+
+        isotp.send(&[1, 2, 3]).unwrap();
+
+        assert_eq!(sent(&isotp), [[0x03, 1, 2, 3, 0xcc, 0xcc, 0xcc, 0xcc]]);
+        assert!(isotp.can.tx.iter().all(|frame| frame.id() == id(TX_ID)));
+    }
+
+    #[test]
+    fn sends_blocks_with_flow_control_and_st_min() {
+        let payload = payload(30);
+
+        let mut isotp = transport(
+            config(),
+            &[
+                (0, frame(RX_ID, &[0x30, 2, 5])),
+                (0, frame(RX_ID, &[0x30, 2, 5])),
+            ],
+        );
+
+        isotp.send(&payload).unwrap();
+
+        assert_eq!(
+            sent(&isotp),
+            [
+                [&[0x10, 30][..], &payload[..6]].concat(),
+                [&[0x21][..], &payload[6..13]].concat(),
+                [&[0x22][..], &payload[13..20]].concat(),
+                [&[0x23][..], &payload[20..27]].concat(),
+                [&[0x24][..], &payload[27..]].concat(),
+            ]
+        );
+
+        // STmin is only waited for between the frames of a block
+        assert_eq!(isotp.timer.delays, [Duration::from_millis(5); 2]);
+    }
+
+    #[test]
+    fn send_waits_for_late_flow_control() {
+        let mut isotp = transport(config(), &[(500, frame(RX_ID, &[0x30, 0, 0]))]);
+
+        isotp.send(&payload(10)).unwrap();
+
+        assert_eq!(isotp.timer.now(), Duration::from_millis(500));
+        assert_eq!(sent(&isotp).len(), 2);
+    }
+
+    #[test]
+    fn send_times_out_without_flow_control() {
+        let mut isotp = transport(
+            config().flow_control_timeout(Duration::from_millis(100)),
+            &[],
+        );
+
+        assert_eq!(isotp.send(&payload(10)), Err(IsoTpError::Timeout));
+        assert_eq!(isotp.timer.now(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn send_times_out_while_other_ids_are_busy() {
+        let other = (0..200)
+            .map(|at| (at, frame(0x123, &[0x30, 0, 0])))
+            .collect::<Vec<_>>();
+
+        let mut isotp = transport(
+            config().flow_control_timeout(Duration::from_millis(100)),
+            &other,
+        );
+
+        assert_eq!(isotp.send(&payload(10)), Err(IsoTpError::Timeout));
+    }
+
+    #[test]
+    fn send_honours_wait_frames_up_to_the_limit() {
+        let wait = frame(RX_ID, &[0x31, 0, 0]);
+
+        let mut isotp = transport(
+            config().max_wait_frames(2),
+            &[(0, wait), (0, wait), (0, frame(RX_ID, &[0x30, 0, 0]))],
+        );
+
+        isotp.send(&payload(10)).unwrap();
+
+        let mut isotp = transport(config().max_wait_frames(2), &[(0, wait); 3]);
+
+        assert_eq!(isotp.send(&payload(10)), Err(IsoTpError::WaitLimitExceeded));
+    }
+
+    #[test]
+    fn send_fails_on_peer_overflow_and_oversized_payloads() {
+        let mut isotp = transport(config(), &[(0, frame(RX_ID, &[0x32, 0, 0]))]);
+
+        assert_eq!(isotp.send(&payload(10)), Err(IsoTpError::Overflow));
+        assert_eq!(
+            isotp.send(&payload(MAX_PAYLOAD_LEN + 1)),
+            Err(IsoTpError::TooLong)
+        );
+    }
+
+    #[test]
+    fn receives_single_frame_skipping_other_ids() {
+        let mut isotp = transport(
+            config(),
+            &[
+                (0, frame(0x123, &[0x02, 9, 9])),
+                (0, frame(RX_ID, &[0x03, 1, 2, 3])),
+            ],
+        );
+
+        let mut buf = [0; 8];
+
+        assert_eq!(isotp.receive(&mut buf), Ok(3));
+        assert_eq!(buf[..3], [1, 2, 3]);
+    }
+
+    #[test]
+    fn receives_segmented_payload_with_block_size() {
+        let payload = payload(30);
+
+        let mut isotp = transport(
+            config().block_size(2).st_min(Duration::from_micros(300)),
+            &[
+                (0, frame(RX_ID, &[&[0x10, 30][..], &payload[..6]].concat())),
+                (0, frame(RX_ID, &[&[0x21][..], &payload[6..13]].concat())),
+                (0, frame(RX_ID, &[&[0x22][..], &payload[13..20]].concat())),
+                (0, frame(RX_ID, &[&[0x23][..], &payload[20..27]].concat())),
+                (0, frame(RX_ID, &[&[0x24][..], &payload[27..]].concat())),
+            ],
+        );
+
+        let mut buf = [0; 64];
+
+        assert_eq!(isotp.receive(&mut buf), Ok(30));
+        assert_eq!(buf[..30], payload[..]);
+
+        // One flow control frame before each block of two consecutive frames
+        assert_eq!(sent(&isotp), [[0x30, 2, 0xf3], [0x30, 2, 0xf3]]);
+    }
+
+    #[test]
+    fn receives_back_to_back_frames_overflowing_a_poll_interval() {
+        let payload = payload(FIRST_FRAME_DATA + 20 * CONSECUTIVE_FRAME_DATA);
+
+        // With the default block size and STmin of 0 the peer sends all consecutive frames
+        // at once, one every 250us, so four times the RX queue arrives within one tick
+        let mut rx = std::vec![(
+            Duration::from_millis(0),
+            frame(
+                RX_ID,
+                &[
+                    &[0x10, payload.len() as u8][..],
+                    &payload[..FIRST_FRAME_DATA]
+                ]
+                .concat(),
+            ),
+        )];
+
+        for (index, chunk) in payload[FIRST_FRAME_DATA..]
+            .chunks(CONSECUTIVE_FRAME_DATA)
+            .enumerate()
+        {
+            let sequence = CONSECUTIVE_FRAME | ((index + 1) % 16) as u8;
+
+            rx.push((
+                Duration::from_micros(250 * (index as u64 + 1)),
+                frame(RX_ID, &[&[sequence][..], chunk].concat()),
+            ));
+        }
+
+        let mut isotp = transport(config(), &[]);
+        isotp.can.rx = rx.into();
+
+        let mut buf = [0; 256];
+
+        assert_eq!(isotp.receive(&mut buf), Ok(payload.len()));
+        assert_eq!(buf[..payload.len()], payload[..]);
+        assert!(isotp.timer.delays.is_empty());
+    }
+
+    #[test]
+    fn receive_times_out_on_missing_consecutive_frame() {
+        let payload = payload(20);
+
+        let mut isotp = transport(
+            config().consecutive_frame_timeout(Duration::from_millis(100)),
+            &[
+                (0, frame(RX_ID, &[&[0x10, 20][..], &payload[..6]].concat())),
+                (0, frame(RX_ID, &[&[0x21][..], &payload[6..13]].concat())),
+            ],
+        );
+
+        let mut buf = [0; 64];
+
+        assert_eq!(isotp.receive(&mut buf), Err(IsoTpError::Timeout));
+        assert_eq!(isotp.timer.now(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn receive_rejects_wrong_sequence_number() {
+        let payload = payload(20);
+
+        let mut isotp = transport(
+            config(),
+            &[
+                (0, frame(RX_ID, &[&[0x10, 20][..], &payload[..6]].concat())),
+                (0, frame(RX_ID, &[&[0x22][..], &payload[6..13]].concat())),
+            ],
+        );
+
+        let mut buf = [0; 64];
+
+        assert_eq!(
+            isotp.receive(&mut buf),
+            Err(IsoTpError::WrongSequenceNumber)
+        );
+    }
+
+    #[test]
+    fn receive_reports_overflow_to_the_peer() {
+        let mut isotp = transport(
+            config(),
+            &[(0, frame(RX_ID, &[0x10, 20, 0, 1, 2, 3, 4, 5]))],
+        );
+
+        let mut buf = [0; 10];
+
+        assert_eq!(isotp.receive(&mut buf), Err(IsoTpError::Overflow));
+        assert_eq!(sent(&isotp), [[0x32, 0, 0]]);
+    }
+}
@@ -0,0 +1,322 @@
+//! High resolution timers, based on the ESP-IDF `esp_timer` service.
+//!
+//! Unlike [`crate::delay::FreeRtos`], the timers have microsecond resolution, and unlike
+//! [`crate::delay::Ets`], waiting for them does not keep the CPU busy.
+//!
+//! # Example
+//!
+//! ```
+//! use esp_idf_hal::esp_timer::*;
+//!
+//! let service = EspTimerService::new()?;
+//!
+//! let mut timer = service.timer(|| info!("Tick"))?;
+//! timer.every(Duration::from_millis(250))?;
+//!
+//! let mut delay = EspTimerDelay::new()?;
+//! delay.delay_us(150).await?;
+//! ```
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "alloc")]
+use core::future::Future;
+#[cfg(feature = "alloc")]
+use core::pin::Pin;
+use core::ptr;
+#[cfg(feature = "alloc")]
+use core::task::{Context, Poll};
+use core::time::Duration;
+
+use esp_idf_sys::*;
+
+#[cfg(all(feature = "alloc", esp_idf_esp_timer_supports_isr_dispatch_method))]
+use crate::interrupt;
+#[cfg(feature = "alloc")]
+use crate::interrupt::asynch::HalIsrNotification;
+
+/// Time elapsed since the timer service was started during boot
+pub fn now() -> Duration {
+    Duration::from_micros(unsafe { esp_timer_get_time() } as u64)
+}
+
+/// Creates [`EspTimer`]s which run their callbacks either from the `esp_timer` task
+/// or directly from the timer ISR
+#[cfg(feature = "alloc")]
+#[derive(Debug, Copy, Clone)]
+pub struct EspTimerService {
+    dispatch_method: esp_timer_dispatch_t,
+}
+
+#[cfg(feature = "alloc")]
+impl EspTimerService {
+    /// Callbacks run in the `esp_timer` task
+    pub fn new() -> Result<Self, EspError> {
+        Self::init()?;
+
+        Ok(Self {
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+        })
+    }
+
+    /// Callbacks run in the timer ISR
+    ///
+    /// # Safety
+    ///
+    /// The callbacks must not block, allocate or otherwise call functions which are
+    /// not allowed in an ISR. Functions further down the callbacks which yield (e.g. by
+    /// notifying a task) make the ISR yield once the callback returns.
+    #[cfg(esp_idf_esp_timer_supports_isr_dispatch_method)]
+    pub unsafe fn isr() -> Result<Self, EspError> {
+        Self::init()?;
+
+        Ok(Self {
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_ISR,
+        })
+    }
+
+    pub fn now(&self) -> Duration {
+        now()
+    }
+
+    /// Create a timer which calls `callback` once scheduled
+    pub fn timer(&self, callback: impl FnMut() + Send + 'static) -> Result<EspTimer, EspError> {
+        let mut callback: Box<Box<dyn FnMut() + Send>> = Box::new(Box::new(callback));
+
+        #[allow(unused_mut)]
+        let mut handler: unsafe extern "C" fn(*mut c_types::c_void) = handle_task;
+
+        #[cfg(esp_idf_esp_timer_supports_isr_dispatch_method)]
+        if self.dispatch_method == esp_timer_dispatch_t_ESP_TIMER_ISR {
+            handler = handle_isr;
+        }
+
+        let handle = create(
+            handler,
+            &mut *callback as *mut Box<dyn FnMut() + Send> as *mut _,
+            self.dispatch_method,
+        )?;
+
+        Ok(EspTimer {
+            handle,
+            _callback: callback,
+        })
+    }
+
+    fn init() -> Result<(), EspError> {
+        // The service is usually initialized during startup already
+        match unsafe { esp_timer_init() } {
+            ESP_OK | ESP_ERR_INVALID_STATE => Ok(()),
+            err => Err(EspError::from(err).unwrap()),
+        }
+    }
+}
+
+/// A timer created by [`EspTimerService::timer`]; dropping it cancels it
+#[cfg(feature = "alloc")]
+pub struct EspTimer {
+    handle: esp_timer_handle_t,
+    _callback: Box<Box<dyn FnMut() + Send>>,
+}
+
+#[cfg(feature = "alloc")]
+impl EspTimer {
+    /// Call the callback once, after `duration`; reschedules the timer if it is already scheduled
+    pub fn after(&mut self, duration: Duration) -> Result<(), EspError> {
+        self.cancel()?;
+
+        esp!(unsafe { esp_timer_start_once(self.handle, duration.as_micros() as u64) })
+    }
+
+    /// Call the callback every `duration`; reschedules the timer if it is already scheduled
+    pub fn every(&mut self, duration: Duration) -> Result<(), EspError> {
+        self.cancel()?;
+
+        esp!(unsafe { esp_timer_start_periodic(self.handle, duration.as_micros() as u64) })
+    }
+
+    /// Returns true if the timer was scheduled
+    pub fn cancel(&mut self) -> Result<bool, EspError> {
+        stop(self.handle)
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        unsafe { esp_timer_is_active(self.handle) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl Send for EspTimer {}
+
+#[cfg(feature = "alloc")]
+impl Drop for EspTimer {
+    fn drop(&mut self) {
+        let _ = stop(self.handle);
+        let _ = esp!(unsafe { esp_timer_delete(self.handle) });
+    }
+}
+
+/// Async delay provider based on an `esp_timer`
+///
+/// The task awaiting the delays is free to run other futures meanwhile. With the
+/// `embedded-hal-async` feature, it implements the `DelayUs` trait of `embedded-hal-async`.
+#[cfg(feature = "alloc")]
+pub struct EspTimerDelay {
+    handle: esp_timer_handle_t,
+    notification: Box<HalIsrNotification>,
+}
+
+#[cfg(feature = "alloc")]
+impl EspTimerDelay {
+    pub fn new() -> Result<Self, EspError> {
+        EspTimerService::init()?;
+
+        let notification = Box::new(HalIsrNotification::new());
+
+        let handle = create(
+            handle_delay,
+            &*notification as *const HalIsrNotification as *mut _,
+            esp_timer_dispatch_t_ESP_TIMER_TASK,
+        )?;
+
+        Ok(Self {
+            handle,
+            notification,
+        })
+    }
+
+    pub fn delay_us(&mut self, us: u32) -> EspTimerDelayFuture<'_> {
+        self.delay(us as u64)
+    }
+
+    pub fn delay_ms(&mut self, ms: u32) -> EspTimerDelayFuture<'_> {
+        self.delay(ms as u64 * 1000)
+    }
+
+    /// Starts the timer right away; the returned future completes once it expires
+    fn delay(&mut self, us: u64) -> EspTimerDelayFuture<'_> {
+        // A previous delay might have been dropped before its timer expired
+        let started = stop(self.handle).and_then(|_| {
+            self.notification.reset();
+
+            esp!(unsafe { esp_timer_start_once(self.handle, us) })
+        });
+
+        EspTimerDelayFuture {
+            notification: &self.notification,
+            started,
+        }
+    }
+}
+
+/// Future returned by [`EspTimerDelay::delay_us`] and [`EspTimerDelay::delay_ms`]
+#[cfg(feature = "alloc")]
+pub struct EspTimerDelayFuture<'a> {
+    notification: &'a HalIsrNotification,
+    started: Result<(), EspError>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Future for EspTimerDelayFuture<'a> {
+    type Output = Result<(), EspError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.started?;
+
+        self.notification.poll_wait(cx).map(Ok)
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "embedded-hal-async"))]
+impl embedded_hal_async::delay::DelayUs for EspTimerDelay {
+    type Error = EspError;
+
+    type DelayUsFuture<'a>
+    where
+        Self: 'a,
+    = EspTimerDelayFuture<'a>;
+
+    fn delay_us(&mut self, us: u32) -> Self::DelayUsFuture<'_> {
+        EspTimerDelay::delay_us(self, us)
+    }
+
+    type DelayMsFuture<'a>
+    where
+        Self: 'a,
+    = EspTimerDelayFuture<'a>;
+
+    fn delay_ms(&mut self, ms: u32) -> Self::DelayMsFuture<'_> {
+        EspTimerDelay::delay_ms(self, ms)
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl Send for EspTimerDelay {}
+
+#[cfg(feature = "alloc")]
+impl Drop for EspTimerDelay {
+    fn drop(&mut self) {
+        let _ = stop(self.handle);
+        let _ = esp!(unsafe { esp_timer_delete(self.handle) });
+    }
+}
+
+pub(crate) fn create(
+    callback: unsafe extern "C" fn(*mut c_types::c_void),
+    arg: *mut c_types::c_void,
+    dispatch_method: esp_timer_dispatch_t,
+) -> Result<esp_timer_handle_t, EspError> {
+    let mut handle: esp_timer_handle_t = ptr::null_mut();
+
+    esp!(unsafe {
+        esp_timer_create(
+            &esp_timer_create_args_t {
+                callback: Some(callback),
+                arg,
+                dispatch_method,
+                name: b"rust\0".as_ptr() as *const _,
+                skip_unhandled_events: false,
+            },
+            &mut handle,
+        )
+    })?;
+
+    Ok(handle)
+}
+
+/// Returns true if the timer was scheduled
+pub(crate) fn stop(handle: esp_timer_handle_t) -> Result<bool, EspError> {
+    match unsafe { esp_timer_stop(handle) } {
+        ESP_OK => Ok(true),
+        ESP_ERR_INVALID_STATE => Ok(false),
+        err => Err(EspError::from(err).unwrap()),
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe extern "C" fn handle_task(arg: *mut c_types::c_void) {
+    let callback = &mut *(arg as *mut Box<dyn FnMut() + Send>);
+
+    callback();
+}
+
+#[cfg(feature = "alloc")]
+unsafe extern "C" fn handle_delay(arg: *mut c_types::c_void) {
+    let notification = &*(arg as *const HalIsrNotification);
+
+    notification.notify();
+}
+
+/// Calls the callback, deferring yields requested meanwhile until the ISR returns
+#[cfg(all(feature = "alloc", esp_idf_esp_timer_supports_isr_dispatch_method))]
+unsafe extern "C" fn handle_isr(arg: *mut c_types::c_void) {
+    let callback = &mut *(arg as *mut Box<dyn FnMut() + Send>);
+
+    if interrupt::with_deferred_isr_yield(callback) {
+        esp_timer_isr_dispatch_need_yield();
+    }
+}
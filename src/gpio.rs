@@ -186,6 +186,31 @@ fn enable_isr_service() -> Result<(), EspError> {
     Ok(())
 }
 
+/// Install the GPIO ISR service with custom interrupt allocation flags
+///
+/// Otherwise, the service is installed with the default flags when the first pin
+/// is subscribed to. As the subscribed callbacks are not in IRAM, `flags` must not
+/// contain [`crate::interrupt::InterruptFlags::iram`]. Fails with `ESP_ERR_INVALID_STATE`
+/// if the service is already installed.
+#[cfg(all(not(feature = "riscv-ulp-hal"), feature = "alloc"))]
+pub fn install_isr_service(flags: crate::interrupt::InterruptFlags) -> Result<(), EspError> {
+    if flags.is_iram() {
+        return Err(EspError::from(esp_idf_sys::ESP_ERR_INVALID_ARG as i32).unwrap());
+    }
+
+    flags.validate()?;
+
+    let mut service_enabled = ISR_SERVICE_ENABLED.lock();
+    if *service_enabled {
+        return Err(EspError::from(esp_idf_sys::ESP_ERR_INVALID_STATE as i32).unwrap());
+    }
+
+    esp!(unsafe { esp_idf_sys::gpio_install_isr_service(flags.into()) })?;
+    *service_enabled = true;
+
+    Ok(())
+}
+
 #[cfg(all(not(feature = "riscv-ulp-hal"), feature = "alloc"))]
 type ClosureBox = Box<Box<dyn FnMut()>>;
 
@@ -229,6 +254,19 @@ impl Drop for PinNotifySubscription {
     }
 }
 
+/// Returns true if `pin` is the number of a GPIO existing on the chip
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub fn is_valid_pin(pin: i32) -> bool {
+    (0..chip::GPIO_COUNT).contains(&pin) && !chip::GPIO_GAPS.contains(&pin)
+}
+
+/// Returns true if `pin` is the number of a GPIO existing on the chip
+/// which is capable of operating as an output
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub fn is_valid_output_pin(pin: i32) -> bool {
+    is_valid_pin(pin) && !chip::INPUT_ONLY_GPIOS.contains(&pin)
+}
+
 /// Interrupt types
 #[cfg(all(not(feature = "riscv-ulp-hal"), feature = "alloc"))]
 pub enum InterruptType {
@@ -1026,6 +1064,15 @@ mod chip {
         None, None, None, None, None, None, None, None, None, None,
     ];
 
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub(crate) const GPIO_COUNT: i32 = 40;
+
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub(crate) const GPIO_GAPS: &[i32] = &[20, 24, 28, 29, 30, 31];
+
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub(crate) const INPUT_ONLY_GPIOS: &[i32] = &[34, 35, 36, 37, 38, 39];
+
     // NOTE: Gpio26 - Gpio32 are used by SPI0/SPI1 for external PSRAM/SPI Flash and
     //       are not recommended for other uses
     pin!(Gpio0:0, IO, RTC:11, ADC2:1, NODAC:0, TOUCH:1);
@@ -1211,6 +1258,21 @@ mod chip {
         None, None, None, None,
     ];
 
+    #[cfg(all(esp32s2, not(feature = "riscv-ulp-hal")))]
+    pub(crate) const GPIO_COUNT: i32 = 47;
+
+    #[cfg(all(esp32s3, not(feature = "riscv-ulp-hal")))]
+    pub(crate) const GPIO_COUNT: i32 = 49;
+
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub(crate) const GPIO_GAPS: &[i32] = &[22, 23, 24, 25];
+
+    #[cfg(all(esp32s2, not(feature = "riscv-ulp-hal")))]
+    pub(crate) const INPUT_ONLY_GPIOS: &[i32] = &[46];
+
+    #[cfg(all(esp32s3, not(feature = "riscv-ulp-hal")))]
+    pub(crate) const INPUT_ONLY_GPIOS: &[i32] = &[];
+
     // NOTE: Gpio26 - Gpio32 (and Gpio33 - Gpio37 if using Octal RAM/Flash) are used
     //       by SPI0/SPI1 for external PSRAM/SPI Flash and are not recommended for
     //       other uses
@@ -1457,6 +1519,12 @@ mod chip {
         None, None, None, None, None, None, None,
     ];
 
+    pub(crate) const GPIO_COUNT: i32 = 22;
+
+    pub(crate) const GPIO_GAPS: &[i32] = &[];
+
+    pub(crate) const INPUT_ONLY_GPIOS: &[i32] = &[];
+
     // NOTE: Gpio12 - Gpio17 are used by SPI0/SPI1 for external PSRAM/SPI Flash and
     //       are not recommended for other uses
     pin!(Gpio0:0,   IO,   RTC:0,  ADC1:0, NODAC:0, NOTOUCH:0);
@@ -1,19 +1,90 @@
-use core::marker::PhantomData;
+use esp_idf_sys::*;
 
 use crate::adc;
+use crate::gpio::{Gpio36, Gpio39, Unknown};
 
-pub struct HallSensor(PhantomData<*const ()>);
+/// Maximum number of samples averaged by [`HallSensor::read_averaged`] and [`HallSensor::calibrate`]
+pub const MAX_AVERAGED_SAMPLES: usize = 1024;
+
+/// The Hall sensor of the ESP32
+///
+/// The sensor is read through ADC1 channels 0 and 3, so it owns GPIO36 and GPIO39
+/// while it exists.
+pub struct HallSensor {
+    gpio36: Gpio36<Unknown>,
+    gpio39: Gpio39<Unknown>,
+    offset: i32,
+}
 
 impl HallSensor {
-    /// # Safety
+    pub fn new<M36: Send, M39: Send>(
+        gpio36: Gpio36<M36>,
+        gpio39: Gpio39<M39>,
+    ) -> Result<Self, EspError> {
+        Ok(HallSensor {
+            gpio36: gpio36.into_unknown()?,
+            gpio39: gpio39.into_unknown()?,
+            offset: 0,
+        })
+    }
+
+    pub fn release(self) -> (Gpio36<Unknown>, Gpio39<Unknown>) {
+        (self.gpio36, self.gpio39)
+    }
+
+    /// Read the sensor output, which is signed depending on the polarity of the magnetic field
     ///
-    /// Care should be taken not to instnatiate this Hall Sensor instance, if it is already instantiated and used elsewhere
-    pub unsafe fn new() -> Self {
-        HallSensor(PhantomData)
+    /// The offset measured by [`HallSensor::calibrate`] is subtracted.
+    pub fn read_raw(&mut self, adc: &mut adc::PoweredAdc<adc::ADC1>) -> Result<i32, EspError> {
+        Ok(adc.read_hall_raw()? as i32 - self.offset)
     }
-}
 
-unsafe impl Send for HallSensor {}
+    /// Read the mean of `samples` sensor outputs, see [`HallSensor::read_raw`]
+    pub fn read_averaged(
+        &mut self,
+        adc: &mut adc::PoweredAdc<adc::ADC1>,
+        samples: usize,
+    ) -> Result<i32, EspError> {
+        Ok(Self::mean(adc, samples)? - self.offset)
+    }
+
+    /// Measure the output of the sensor without a magnetic field as the mean of `samples`
+    /// outputs, and subtract it from subsequent reads
+    ///
+    /// Returns the measured offset.
+    pub fn calibrate(
+        &mut self,
+        adc: &mut adc::PoweredAdc<adc::ADC1>,
+        samples: usize,
+    ) -> Result<i32, EspError> {
+        self.offset = Self::mean(adc, samples)?;
+
+        Ok(self.offset)
+    }
+
+    /// The offset subtracted from the sensor output
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: i32) {
+        self.offset = offset;
+    }
+
+    fn mean(adc: &mut adc::PoweredAdc<adc::ADC1>, samples: usize) -> Result<i32, EspError> {
+        if !(1..=MAX_AVERAGED_SAMPLES).contains(&samples) {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        let mut sum = 0_i64;
+
+        for _ in 0..samples {
+            sum += adc.read_hall_raw()? as i64;
+        }
+
+        Ok((sum / samples as i64) as i32)
+    }
+}
 
 impl embedded_hal_0_2::adc::Channel<adc::ADC1> for HallSensor {
     type ID = ();
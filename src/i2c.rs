@@ -22,16 +22,42 @@ pub struct SlavePins<SDA: OutputPin + InputPin, SCL: InputPin> {
 
 /// I2C configuration
 pub mod config {
+    use crate::interrupt::InterruptFlags;
     use crate::units::*;
     use core::time::Duration;
 
+    /// SDA sample and hold times, in APB clock cycles
+    ///
+    /// The sample time is counted from the rising edge of SCL, the hold time from
+    /// the falling edge of SCL.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct DataTiming {
+        pub sample_time: Ticks,
+        pub hold_time: Ticks,
+    }
+
+    /// Setup and hold times of a START or STOP condition, in APB clock cycles
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct ConditionTiming {
+        pub setup_time: Ticks,
+        pub hold_time: Ticks,
+    }
+
     /// I2C Master configuration
+    ///
+    /// Timing options left as `None` keep the defaults computed by ESP-IDF for the baudrate.
     #[derive(Copy, Clone)]
     pub struct MasterConfig {
         pub baudrate: Hertz,
         pub timeout: Option<Duration>,
         pub sda_pullup_enabled: bool,
         pub scl_pullup_enabled: bool,
+        pub clock_stretch_timeout: Option<Ticks>,
+        pub data_timing: Option<DataTiming>,
+        pub start_timing: Option<ConditionTiming>,
+        pub stop_timing: Option<ConditionTiming>,
+        pub glitch_filter: Option<Ticks>,
+        pub intr_flags: InterruptFlags,
     }
 
     impl MasterConfig {
@@ -62,6 +88,50 @@ pub mod config {
             self.scl_pullup_enabled = enable;
             self
         }
+
+        /// Maximum time a slave may stretch the clock before the transaction is aborted
+        ///
+        /// On the ESP32 and ESP32-S2 the timeout is a number of APB clock cycles. On the
+        /// ESP32-S3 and ESP32-C3 it is the exponent of a power of two number of APB clock
+        /// cycles instead. The valid range is `1..=`[`super::MAX_CLOCK_STRETCH_TIMEOUT`].
+        #[must_use]
+        pub fn clock_stretch_timeout(mut self, timeout: Option<Ticks>) -> Self {
+            self.clock_stretch_timeout = timeout;
+            self
+        }
+
+        #[must_use]
+        pub fn data_timing(mut self, timing: Option<DataTiming>) -> Self {
+            self.data_timing = timing;
+            self
+        }
+
+        #[must_use]
+        pub fn start_timing(mut self, timing: Option<ConditionTiming>) -> Self {
+            self.start_timing = timing;
+            self
+        }
+
+        #[must_use]
+        pub fn stop_timing(mut self, timing: Option<ConditionTiming>) -> Self {
+            self.stop_timing = timing;
+            self
+        }
+
+        /// Enables the SCL/SDA glitch filter, ignoring pulses shorter than the
+        /// given number of APB clock cycles (0 - 7)
+        #[must_use]
+        pub fn glitch_filter(mut self, threshold: Option<Ticks>) -> Self {
+            self.glitch_filter = threshold;
+            self
+        }
+
+        /// Flags for the allocation of the driver interrupt
+        #[must_use]
+        pub fn intr_flags(mut self, flags: InterruptFlags) -> Self {
+            self.intr_flags = flags;
+            self
+        }
     }
 
     impl Default for MasterConfig {
@@ -71,8 +141,49 @@ pub mod config {
                 timeout: None,
                 sda_pullup_enabled: true,
                 scl_pullup_enabled: true,
+                clock_stretch_timeout: None,
+                data_timing: None,
+                start_timing: None,
+                stop_timing: None,
+                glitch_filter: None,
+                intr_flags: Default::default(),
+            }
+        }
+    }
+
+    /// Register pointer model used by a slave emulating a register-based device
+    ///
+    /// With a register pointer model, the first byte(s) of every write transaction
+    /// are interpreted as the register address the transaction starts at.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum RegisterPointer {
+        /// No register pointer; transactions are reported as raw bytes
+        None,
+        /// 8-bit register pointer
+        U8,
+        /// 16-bit big-endian register pointer (e.g. larger EEPROMs)
+        U16,
+    }
+
+    impl RegisterPointer {
+        /// Number of bytes used by the register pointer on the wire
+        pub fn len(&self) -> usize {
+            match self {
+                Self::None => 0,
+                Self::U8 => 1,
+                Self::U16 => 2,
             }
         }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    impl Default for RegisterPointer {
+        fn default() -> Self {
+            Self::None
+        }
     }
 
     /// I2C Slave configuration
@@ -83,6 +194,10 @@ pub mod config {
         pub scl_pullup_enabled: bool,
         pub rx_buf_len: usize,
         pub tx_buf_len: usize,
+        pub register_pointer: RegisterPointer,
+        pub register_auto_increment: bool,
+        pub transaction_gap: Duration,
+        pub intr_flags: InterruptFlags,
     }
 
     impl SlaveConfig {
@@ -119,6 +234,38 @@ pub mod config {
             self.tx_buf_len = len;
             self
         }
+
+        #[must_use]
+        pub fn register_pointer(mut self, register_pointer: RegisterPointer) -> Self {
+            self.register_pointer = register_pointer;
+            self
+        }
+
+        /// Whether the register pointer advances with every byte written or read,
+        /// as is the case with most EEPROMs and sensors
+        #[must_use]
+        pub fn register_auto_increment(mut self, enable: bool) -> Self {
+            self.register_auto_increment = enable;
+            self
+        }
+
+        /// Bus idle time after which a transaction is considered complete
+        ///
+        /// The ESP-IDF slave driver does not report START/STOP conditions, so
+        /// transaction boundaries are inferred from gaps in the received data.
+        /// Note that the effective resolution is one FreeRTOS tick.
+        #[must_use]
+        pub fn transaction_gap(mut self, gap: Duration) -> Self {
+            self.transaction_gap = gap;
+            self
+        }
+
+        /// Flags for the allocation of the driver interrupt
+        #[must_use]
+        pub fn intr_flags(mut self, flags: InterruptFlags) -> Self {
+            self.intr_flags = flags;
+            self
+        }
     }
 
     impl Default for SlaveConfig {
@@ -129,11 +276,26 @@ pub mod config {
                 scl_pullup_enabled: true,
                 rx_buf_len: 0,
                 tx_buf_len: 0,
+                register_pointer: RegisterPointer::None,
+                register_auto_increment: true,
+                transaction_gap: Duration::from_millis(1),
+                intr_flags: Default::default(),
             }
         }
     }
 }
 
+/// Largest clock stretching timeout supported by the chip
+///
+/// This is a number of APB clock cycles on the ESP32 and ESP32-S2, and the exponent
+/// of a power of two number of APB clock cycles on the ESP32-S3 and ESP32-C3.
+#[cfg(esp32)]
+pub const MAX_CLOCK_STRETCH_TIMEOUT: u32 = 0xfffff;
+#[cfg(esp32s2)]
+pub const MAX_CLOCK_STRETCH_TIMEOUT: u32 = 0xffffff;
+#[cfg(any(esp32s3, esp32c3))]
+pub const MAX_CLOCK_STRETCH_TIMEOUT: u32 = 0x1f;
+
 pub trait I2c: Send {
     fn port() -> i2c_port_t;
 }
@@ -151,6 +313,24 @@ where
     timeout: TickType_t,
 }
 
+/// Event observed by a [`Slave`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SlaveEvent<'a> {
+    /// The master wrote `data` to the slave.
+    ///
+    /// When a register pointer model is configured, `register` is the register
+    /// the data is written to, and `data` does not include the pointer bytes.
+    Write {
+        register: Option<u16>,
+        data: &'a [u8],
+    },
+    /// The master wrote only a register pointer, which is how register reads start.
+    ///
+    /// The slave should now preload its transmit buffer with the contents of
+    /// the register map starting at `register`.
+    ReadRequest { register: u16 },
+}
+
 pub struct Slave<I2C, SDA, SCL>
 where
     I2C: I2c,
@@ -160,6 +340,10 @@ where
     i2c: I2C,
     pins: SlavePins<SDA, SCL>,
     timeout: TickType_t,
+    gap: TickType_t,
+    register_pointer: config::RegisterPointer,
+    register_auto_increment: bool,
+    register: u16,
 }
 
 unsafe impl<I2C: I2c, SDA: OutputPin + InputPin, SCL: InputPin> Send for Slave<I2C, SDA, SCL> {}
@@ -180,6 +364,8 @@ where
             return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
         }
 
+        config.intr_flags.validate()?;
+
         let sys_config = i2c_config_t {
             mode: i2c_mode_t_I2C_MODE_MASTER,
             sda_io_num: pins.sda.pin(),
@@ -202,17 +388,144 @@ where
                 i2c_mode_t_I2C_MODE_MASTER,
                 0, // Not used in master mode
                 0, // Not used in master mode
-                0,
-            ) // TODO: set flags
+                config.intr_flags.into(),
+            )
         })?;
 
-        Ok(Master {
+        let mut master = Master {
             i2c,
             pins,
             timeout: TickType::from(config.timeout).0,
+        };
+
+        if let Err(e) = master.configure(&config) {
+            unsafe { i2c_driver_delete(I2C::port()) };
+
+            return Err(e);
+        }
+
+        Ok(master)
+    }
+
+    fn configure(&mut self, config: &config::MasterConfig) -> Result<(), EspError> {
+        if let Some(timeout) = config.clock_stretch_timeout {
+            self.set_clock_stretch_timeout(timeout)?;
+        }
+
+        if let Some(timing) = config.data_timing {
+            self.set_data_timing(timing)?;
+        }
+
+        if let Some(timing) = config.start_timing {
+            self.set_start_timing(timing)?;
+        }
+
+        if let Some(timing) = config.stop_timing {
+            self.set_stop_timing(timing)?;
+        }
+
+        self.set_glitch_filter(config.glitch_filter)
+    }
+
+    /// Change the maximum time a slave may stretch the clock, see
+    /// [`config::MasterConfig::clock_stretch_timeout`] for the unit
+    pub fn set_clock_stretch_timeout(&mut self, timeout: Ticks) -> Result<(), EspError> {
+        if !(1..=MAX_CLOCK_STRETCH_TIMEOUT).contains(&timeout.0) {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        esp!(unsafe { i2c_set_timeout(I2C::port(), timeout.0 as _) })
+    }
+
+    /// Returns the current clock stretching timeout, see
+    /// [`config::MasterConfig::clock_stretch_timeout`] for the unit
+    pub fn clock_stretch_timeout(&self) -> Result<Ticks, EspError> {
+        let mut timeout = 0;
+        esp!(unsafe { i2c_get_timeout(I2C::port(), &mut timeout) })?;
+
+        Ok(Ticks(timeout as _))
+    }
+
+    /// Change the SDA sample and hold times
+    pub fn set_data_timing(&mut self, timing: config::DataTiming) -> Result<(), EspError> {
+        esp!(unsafe {
+            i2c_set_data_timing(
+                I2C::port(),
+                timing.sample_time.0 as _,
+                timing.hold_time.0 as _,
+            )
         })
     }
 
+    /// Returns the current SDA sample and hold times
+    pub fn data_timing(&self) -> Result<config::DataTiming, EspError> {
+        let mut sample_time = 0;
+        let mut hold_time = 0;
+        esp!(unsafe { i2c_get_data_timing(I2C::port(), &mut sample_time, &mut hold_time) })?;
+
+        Ok(config::DataTiming {
+            sample_time: Ticks(sample_time as _),
+            hold_time: Ticks(hold_time as _),
+        })
+    }
+
+    /// Change the setup and hold times of the START condition
+    pub fn set_start_timing(&mut self, timing: config::ConditionTiming) -> Result<(), EspError> {
+        esp!(unsafe {
+            i2c_set_start_timing(
+                I2C::port(),
+                timing.setup_time.0 as _,
+                timing.hold_time.0 as _,
+            )
+        })
+    }
+
+    /// Returns the current setup and hold times of the START condition
+    pub fn start_timing(&self) -> Result<config::ConditionTiming, EspError> {
+        let mut setup_time = 0;
+        let mut hold_time = 0;
+        esp!(unsafe { i2c_get_start_timing(I2C::port(), &mut setup_time, &mut hold_time) })?;
+
+        Ok(config::ConditionTiming {
+            setup_time: Ticks(setup_time as _),
+            hold_time: Ticks(hold_time as _),
+        })
+    }
+
+    /// Change the setup and hold times of the STOP condition
+    pub fn set_stop_timing(&mut self, timing: config::ConditionTiming) -> Result<(), EspError> {
+        esp!(unsafe {
+            i2c_set_stop_timing(
+                I2C::port(),
+                timing.setup_time.0 as _,
+                timing.hold_time.0 as _,
+            )
+        })
+    }
+
+    /// Returns the current setup and hold times of the STOP condition
+    pub fn stop_timing(&self) -> Result<config::ConditionTiming, EspError> {
+        let mut setup_time = 0;
+        let mut hold_time = 0;
+        esp!(unsafe { i2c_get_stop_timing(I2C::port(), &mut setup_time, &mut hold_time) })?;
+
+        Ok(config::ConditionTiming {
+            setup_time: Ticks(setup_time as _),
+            hold_time: Ticks(hold_time as _),
+        })
+    }
+
+    /// Enable (with the given threshold in APB clock cycles) or disable the SCL/SDA glitch filter
+    pub fn set_glitch_filter(&mut self, threshold: Option<Ticks>) -> Result<(), EspError> {
+        match threshold {
+            Some(threshold) if threshold.0 > 7 => {
+                Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+            }
+            Some(threshold) => esp!(unsafe { i2c_filter_enable(I2C::port(), threshold.0 as _) }),
+            None => esp!(unsafe { i2c_filter_disable(I2C::port()) }),
+        }
+    }
+
     pub fn release(self) -> Result<(I2C, MasterPins<SDA, SCL>), EspError> {
         esp!(unsafe { i2c_driver_delete(I2C::port()) })?;
 
@@ -455,12 +768,43 @@ where
     SDA: OutputPin + InputPin,
     SCL: InputPin,
 {
+    /// Create a slave answering to the 7-bit address `slave_addr`
     pub fn new(
         i2c: I2C,
         pins: SlavePins<SDA, SCL>,
         slave_addr: u8,
         config: config::SlaveConfig,
     ) -> Result<Self, EspError> {
+        if slave_addr > 0x7f {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        Self::install(i2c, pins, slave_addr as u16, false, config)
+    }
+
+    /// Create a slave answering to the 10-bit address `slave_addr`
+    pub fn new_10bit(
+        i2c: I2C,
+        pins: SlavePins<SDA, SCL>,
+        slave_addr: u16,
+        config: config::SlaveConfig,
+    ) -> Result<Self, EspError> {
+        if slave_addr > 0x3ff {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        Self::install(i2c, pins, slave_addr, true, config)
+    }
+
+    fn install(
+        i2c: I2C,
+        pins: SlavePins<SDA, SCL>,
+        slave_addr: u16,
+        addr_10bit: bool,
+        config: config::SlaveConfig,
+    ) -> Result<Self, EspError> {
+        config.intr_flags.validate()?;
+
         #[cfg(any(esp_idf_version = "4.4", esp_idf_version_major = "5"))]
         let sys_config = i2c_config_t {
             mode: i2c_mode_t_I2C_MODE_SLAVE,
@@ -470,8 +814,8 @@ where
             scl_pullup_en: config.scl_pullup_enabled,
             __bindgen_anon_1: i2c_config_t__bindgen_ty_1 {
                 slave: i2c_config_t__bindgen_ty_1__bindgen_ty_2 {
-                    slave_addr: slave_addr as u16,
-                    addr_10bit_en: 0, // For now; to become configurable with embedded-hal V1.0
+                    slave_addr,
+                    addr_10bit_en: addr_10bit as _,
                     maximum_speed: 0,
                 },
             },
@@ -487,8 +831,8 @@ where
             scl_pullup_en: config.scl_pullup_enabled,
             __bindgen_anon_1: i2c_config_t__bindgen_ty_1 {
                 slave: i2c_config_t__bindgen_ty_1__bindgen_ty_2 {
-                    slave_addr: slave_addr as u16,
-                    addr_10bit_en: 0, // For now; to become configurable with embedded-hal V1.0
+                    slave_addr,
+                    addr_10bit_en: addr_10bit as _,
                 },
             },
             ..Default::default()
@@ -502,7 +846,7 @@ where
                 i2c_mode_t_I2C_MODE_SLAVE,
                 config.rx_buf_len as u32,
                 config.tx_buf_len as u32,
-                0, // TODO: set flags
+                config.intr_flags.into(),
             )
         })?;
 
@@ -510,6 +854,10 @@ where
             i2c,
             pins,
             timeout: TickType::from(config.timeout).0,
+            gap: core::cmp::max(TickType::from(config.transaction_gap).0, 1),
+            register_pointer: config.register_pointer,
+            register_auto_increment: config.register_auto_increment,
+            register: 0,
         })
     }
 
@@ -555,6 +903,135 @@ where
             Err(EspError::from(ESP_ERR_TIMEOUT as i32).unwrap())
         }
     }
+
+    /// Returns the current value of the register pointer
+    pub fn register(&self) -> u16 {
+        self.register
+    }
+
+    /// Waits for the next transaction addressed to this slave and reports it
+    ///
+    /// Blocks for up to the configured timeout for the transaction to start, and then
+    /// collects the received bytes into `buffer` until the bus has been idle for
+    /// the configured transaction gap.
+    ///
+    /// Data which does not fit in `buffer` is left in the driver ringbuffer and will be
+    /// reported by the next call.
+    pub fn wait_event<'b>(&mut self, buffer: &'b mut [u8]) -> Result<SlaveEvent<'b>, EspError> {
+        if buffer.len() <= self.register_pointer.len() {
+            return Err(EspError::from(ESP_ERR_INVALID_SIZE as i32).unwrap());
+        }
+
+        let mut len = self.read_raw(buffer, self.timeout)?;
+        if len == 0 {
+            return Err(EspError::from(ESP_ERR_TIMEOUT as i32).unwrap());
+        }
+
+        while len < buffer.len() {
+            let n = self.read_raw(&mut buffer[len..], self.gap)?;
+            if n == 0 {
+                break;
+            }
+
+            len += n;
+        }
+
+        let data = &buffer[..len];
+
+        let pointer_len = self.register_pointer.len();
+        if pointer_len == 0 || len < pointer_len {
+            return Ok(SlaveEvent::Write {
+                register: None,
+                data,
+            });
+        }
+
+        let register = match self.register_pointer {
+            config::RegisterPointer::U16 => u16::from_be_bytes([data[0], data[1]]),
+            _ => data[0] as u16,
+        };
+
+        let data = &data[pointer_len..];
+
+        if data.is_empty() {
+            self.register = register;
+
+            Ok(SlaveEvent::ReadRequest { register })
+        } else {
+            self.register = if self.register_auto_increment {
+                register.wrapping_add(data.len() as u16)
+            } else {
+                register
+            };
+
+            Ok(SlaveEvent::Write {
+                register: Some(register),
+                data,
+            })
+        }
+    }
+
+    /// Waits for the next transaction and dispatches it to the supplied callbacks
+    ///
+    /// `on_receive` is called with the register (if a register pointer model is configured)
+    /// and the data written by the master.
+    ///
+    /// `on_request` is called when the master is about to read from `register`. The callback
+    /// should fill the passed buffer with the register contents and return the number of
+    /// valid bytes, which are then queued for transmission. The hardware TX FIFO is reset
+    /// first, but bytes of a previous request still queued in the driver's TX buffer (e.g.
+    /// because the master read fewer bytes than were queued) are sent before the new ones,
+    /// as ESP-IDF offers no way to discard them.
+    pub fn serve<R, Q>(
+        &mut self,
+        buffer: &mut [u8],
+        mut on_receive: R,
+        mut on_request: Q,
+    ) -> Result<(), EspError>
+    where
+        R: FnMut(Option<u16>, &[u8]),
+        Q: FnMut(u16, &mut [u8]) -> usize,
+    {
+        let register = match self.wait_event(buffer)? {
+            SlaveEvent::Write { register, data } => {
+                on_receive(register, data);
+
+                match register {
+                    Some(_) => self.register,
+                    None => return Ok(()),
+                }
+            }
+            SlaveEvent::ReadRequest { register } => register,
+        };
+
+        let len = on_request(register, buffer);
+
+        // Only clears the hardware FIFO, not the driver's TX buffer
+        esp!(unsafe { i2c_reset_tx_fifo(I2C::port()) })?;
+
+        if len > 0 {
+            self.write(&buffer[..len])?;
+        }
+
+        Ok(())
+    }
+
+    fn read_raw(&mut self, buffer: &mut [u8], timeout: TickType_t) -> Result<usize, EspError> {
+        let n = unsafe {
+            i2c_slave_read_buffer(
+                I2C::port(),
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                timeout,
+            )
+        };
+
+        if n >= 0 {
+            Ok(n as usize)
+        } else {
+            Err(EspError::from(ESP_FAIL).unwrap())
+        }
+    }
 }
 
 #[repr(u32)]
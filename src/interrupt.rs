@@ -1,10 +1,15 @@
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use core::cell::{RefCell, RefMut};
 use core::ops::{Deref, DerefMut};
 use core::ptr;
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 
 use esp_idf_sys::*;
 
+use crate::cpu;
+
 /// Returns true if the currently active core is executing an ISR request
 #[inline(always)]
 #[link_section = ".iram1.interrupt_active"]
@@ -12,13 +17,17 @@ pub fn active() -> bool {
     unsafe { xPortInIsrContext() != 0 }
 }
 
-static ISR_YIELDER: AtomicPtr<c_types::c_void> = AtomicPtr::new(ptr::null_mut());
+/// The ISR yield function of each core, as the ISRs of both cores run concurrently
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_ISR_YIELDER: AtomicPtr<c_types::c_void> = AtomicPtr::new(ptr::null_mut());
+static ISR_YIELDER: [AtomicPtr<c_types::c_void>; cpu::CORES as usize] =
+    [NO_ISR_YIELDER; cpu::CORES as usize];
 
 #[inline(always)]
 #[link_section = ".iram1.interrupt_get_isr_yielder"]
 unsafe fn get_isr_yielder() -> Option<unsafe fn()> {
     if active() {
-        let ptr = ISR_YIELDER.load(Ordering::SeqCst);
+        let ptr = ISR_YIELDER[cpu::core() as usize].load(Ordering::SeqCst);
         if ptr.is_null() {
             None
         } else {
@@ -51,7 +60,7 @@ pub unsafe fn set_isr_yielder(yielder: Option<unsafe fn()>) -> Option<unsafe fn(
             ptr::null_mut()
         };
 
-        let ptr = ISR_YIELDER.swap(ptr, Ordering::SeqCst);
+        let ptr = ISR_YIELDER[cpu::core() as usize].swap(ptr, Ordering::SeqCst);
 
         if ptr.is_null() {
             None
@@ -63,12 +72,276 @@ pub unsafe fn set_isr_yielder(yielder: Option<unsafe fn()>) -> Option<unsafe fn(
     }
 }
 
+/// Set by [`defer_isr_yield`] on the active core while [`with_deferred_isr_yield`] runs
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_ISR_YIELD: AtomicBool = AtomicBool::new(false);
+static ISR_YIELD_DEFERRED: [AtomicBool; cpu::CORES as usize] = [NO_ISR_YIELD; cpu::CORES as usize];
+
+#[inline(always)]
+#[link_section = ".iram1.interrupt_defer_isr_yield"]
+unsafe fn defer_isr_yield() {
+    ISR_YIELD_DEFERRED[cpu::core() as usize].store(true, Ordering::SeqCst);
+}
+
+/// Call `f` from an ISR with the yields it requests deferred, and return whether it
+/// requested any, for ISR callbacks of ESP-IDF drivers which yield once told so
+///
+/// Requests are tracked per invocation: the flag of the active core is saved and
+/// restored around `f`, so that an ISR nested into `f` does not lose or take over its requests.
+///
+/// # Safety
+///
+/// Must only be called from an ISR.
+#[inline(always)]
+pub(crate) unsafe fn with_deferred_isr_yield(f: impl FnOnce()) -> bool {
+    let deferred = &ISR_YIELD_DEFERRED[cpu::core() as usize];
+
+    let outer = deferred.swap(false, Ordering::SeqCst);
+    let previous = set_isr_yielder(Some(defer_isr_yield));
+
+    f();
+
+    set_isr_yielder(previous);
+
+    deferred.swap(outer, Ordering::SeqCst)
+}
+
+/// Flags for the allocation of a CPU interrupt, see `esp_intr_alloc`
+///
+/// Without any level, ESP-IDF picks one of the low and medium priority levels (1 to 3).
+///
+/// ## Examples
+///
+/// ```
+/// let flags = InterruptFlags::new().level(3).iram();
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct InterruptFlags(u32);
+
+/// Marks flags given a level outside 1 to 7; never passed to ESP-IDF
+const INVALID_LEVEL: u32 = 1 << 31;
+
+impl InterruptFlags {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Allow the interrupt to have priority `level`; can be called multiple times
+    /// to allow several levels
+    ///
+    /// `level` must be within 1 (lowest) and 7 (NMI), otherwise allocating the interrupt
+    /// or installing the driver fails with `ESP_ERR_INVALID_ARG`.
+    #[must_use]
+    pub fn level(self, level: u8) -> Self {
+        if (1..=7).contains(&level) {
+            Self(self.0 | ESP_INTR_FLAG_LEVEL1 << (level - 1))
+        } else {
+            Self(self.0 | INVALID_LEVEL)
+        }
+    }
+
+    /// Share the interrupt with other peripherals; only allowed for levels 1 to 3
+    #[must_use]
+    pub fn shared(self) -> Self {
+        Self(self.0 | ESP_INTR_FLAG_SHARED)
+    }
+
+    /// Edge-triggered instead of level-triggered interrupt
+    #[must_use]
+    pub fn edge(self) -> Self {
+        Self(self.0 | ESP_INTR_FLAG_EDGE)
+    }
+
+    /// Keep serving the interrupt while the flash cache is disabled; the
+    /// handler and everything it uses must then be in IRAM
+    #[must_use]
+    pub fn iram(self) -> Self {
+        Self(self.0 | ESP_INTR_FLAG_IRAM)
+    }
+
+    /// Allocate the interrupt disabled, see [`InterruptHandle::enable`]
+    #[must_use]
+    pub fn disabled(self) -> Self {
+        Self(self.0 | ESP_INTR_FLAG_INTRDISABLED)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0 & !INVALID_LEVEL
+    }
+
+    pub fn is_iram(&self) -> bool {
+        self.0 & ESP_INTR_FLAG_IRAM != 0
+    }
+
+    /// Check that all levels given to [`InterruptFlags::level`] were within 1 and 7
+    pub fn validate(&self) -> Result<(), EspError> {
+        if self.0 & INVALID_LEVEL != 0 {
+            Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<InterruptFlags> for u32 {
+    fn from(flags: InterruptFlags) -> Self {
+        flags.bits()
+    }
+}
+
+impl From<InterruptFlags> for i32 {
+    fn from(flags: InterruptFlags) -> Self {
+        flags.bits() as _
+    }
+}
+
+/// Allocate a CPU interrupt for the peripheral interrupt `source`
+/// (one of the `ETS_*_INTR_SOURCE` values) and call `handler` when it is raised
+///
+/// The handler runs in an ISR context. As it is not in IRAM, `flags` must not
+/// contain [`InterruptFlags::iram`]; use [`allocate_raw`] for IRAM handlers.
+/// The interrupt is freed when the returned handle is dropped.
+#[cfg(feature = "alloc")]
+pub fn allocate(
+    source: periph_interrput_t,
+    flags: InterruptFlags,
+    handler: impl FnMut() + Send + 'static,
+) -> Result<InterruptHandle, EspError> {
+    if flags.is_iram() {
+        return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+    }
+
+    let mut handler: alloc::boxed::Box<HandlerBox> =
+        alloc::boxed::Box::new(alloc::boxed::Box::new(handler));
+
+    let mut handle = unsafe {
+        allocate_raw(
+            source,
+            flags,
+            call_handler,
+            &mut *handler as *mut HandlerBox as *mut _,
+        )
+    }?;
+
+    handle.handler = Some(handler);
+
+    Ok(handle)
+}
+
+/// Allocate a CPU interrupt for the peripheral interrupt `source` and
+/// call `handler` with `arg` when it is raised
+///
+/// # Safety
+///
+/// `arg` must stay valid for as long as the returned handle exists. With
+/// [`InterruptFlags::iram`], `handler` and everything it uses must be in IRAM,
+/// e.g. by placing it with `#[link_section = ".iram1.my_handler"]`.
+pub unsafe fn allocate_raw(
+    source: periph_interrput_t,
+    flags: InterruptFlags,
+    handler: unsafe extern "C" fn(*mut c_types::c_void),
+    arg: *mut c_types::c_void,
+) -> Result<InterruptHandle, EspError> {
+    flags.validate()?;
+
+    let mut handle: intr_handle_t = ptr::null_mut();
+
+    esp!(esp_intr_alloc(
+        source as _,
+        flags.into(),
+        Some(handler),
+        arg,
+        &mut handle
+    ))?;
+
+    Ok(InterruptHandle {
+        handle,
+        #[cfg(feature = "alloc")]
+        handler: None,
+    })
+}
+
+#[cfg(feature = "alloc")]
+type HandlerBox = alloc::boxed::Box<dyn FnMut() + Send>;
+
+#[cfg(feature = "alloc")]
+unsafe extern "C" fn call_handler(arg: *mut c_types::c_void) {
+    let handler = &mut *(arg as *mut HandlerBox);
+
+    handler();
+}
+
+/// A CPU interrupt allocated by [`allocate`] or [`allocate_raw`]; dropping it frees the interrupt
+pub struct InterruptHandle {
+    handle: intr_handle_t,
+    #[cfg(feature = "alloc")]
+    handler: Option<alloc::boxed::Box<HandlerBox>>,
+}
+
+impl InterruptHandle {
+    /// Enable the interrupt on the CPU it was allocated on
+    pub fn enable(&mut self) -> Result<(), EspError> {
+        esp!(unsafe { esp_intr_enable(self.handle) })
+    }
+
+    pub fn disable(&mut self) -> Result<(), EspError> {
+        esp!(unsafe { esp_intr_disable(self.handle) })
+    }
+
+    /// The CPU the interrupt was allocated on
+    pub fn cpu(&self) -> i32 {
+        unsafe { esp_intr_get_cpu(self.handle) }
+    }
+
+    /// The number of the CPU interrupt
+    pub fn number(&self) -> i32 {
+        unsafe { esp_intr_get_intno(self.handle) }
+    }
+
+    /// Free the interrupt, reporting the error which dropping the handle ignores
+    pub fn free(self) -> Result<(), EspError> {
+        let this = core::mem::ManuallyDrop::new(self);
+
+        let freed = esp!(unsafe { esp_intr_free(this.handle) });
+
+        // The handler must outlive the interrupt, so it is leaked if freeing failed
+        #[cfg(feature = "alloc")]
+        if freed.is_ok() {
+            drop(unsafe { ptr::read(&this.handler) });
+        }
+
+        freed
+    }
+}
+
+unsafe impl Send for InterruptHandle {}
+
+impl Drop for InterruptHandle {
+    fn drop(&mut self) {
+        // Best effort, as the handle is dropped on every driver teardown path
+        if esp!(unsafe { esp_intr_free(self.handle) }).is_err() {
+            #[cfg(feature = "alloc")]
+            core::mem::forget(self.handler.take());
+        }
+    }
+}
+
 pub mod task {
+    #[cfg(feature = "alloc")]
+    extern crate alloc;
+
+    #[cfg(feature = "alloc")]
+    use alloc::boxed::Box;
+    #[cfg(feature = "alloc")]
+    use alloc::sync::Arc;
+
     use core::ptr;
+    use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
     use core::time::Duration;
 
     use esp_idf_sys::*;
 
+    use crate::cpu::Core;
     use crate::delay::TickType;
 
     #[inline(always)]
@@ -206,6 +479,437 @@ pub mod task {
 
         notified != 0
     }
+
+    /// Priority of the current task
+    pub fn priority() -> u32 {
+        unsafe { uxTaskPriorityGet(ptr::null_mut()) }
+    }
+
+    /// Change the priority of the current task
+    pub fn set_priority(priority: u32) {
+        unsafe { vTaskPrioritySet(ptr::null_mut(), priority) }
+    }
+
+    /// The minimum amount of stack (in bytes) which has remained free for the
+    /// current task since it started
+    pub fn stack_high_water_mark() -> usize {
+        unsafe { uxTaskGetStackHighWaterMark(ptr::null_mut()) as _ }
+    }
+
+    /// Configuration of a task created by [`spawn`]
+    #[derive(Debug, Copy, Clone)]
+    pub struct TaskConfig {
+        /// Truncated to `configMAX_TASK_NAME_LEN - 1` bytes
+        pub name: &'static str,
+        /// Stack size in bytes
+        pub stack_size: usize,
+        pub priority: u32,
+        /// The core to pin the task to, or `None` to let it run on any core
+        pub core: Option<Core>,
+    }
+
+    impl TaskConfig {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn name(mut self, name: &'static str) -> Self {
+            self.name = name;
+            self
+        }
+
+        #[must_use]
+        pub fn stack_size(mut self, stack_size: usize) -> Self {
+            self.stack_size = stack_size;
+            self
+        }
+
+        #[must_use]
+        pub fn priority(mut self, priority: u32) -> Self {
+            self.priority = priority;
+            self
+        }
+
+        #[must_use]
+        pub fn core(mut self, core: Option<Core>) -> Self {
+            self.core = core;
+            self
+        }
+    }
+
+    impl TaskConfig {
+        /// The name, truncated and NUL-terminated for `xTaskCreatePinnedToCore`
+        fn c_name(&self) -> [u8; configMAX_TASK_NAME_LEN as usize] {
+            let mut name = [0_u8; configMAX_TASK_NAME_LEN as usize];
+            let len = core::cmp::min(self.name.len(), name.len() - 1);
+            name[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+
+            name
+        }
+    }
+
+    impl Default for TaskConfig {
+        fn default() -> Self {
+            Self {
+                name: "rust",
+                stack_size: 4096,
+                priority: 5,
+                core: None,
+            }
+        }
+    }
+
+    /// Run `f` in a new FreeRTOS task created according to `config`
+    ///
+    /// The task is deleted once `f` returns; its result can be retrieved with [`JoinHandle::join`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// let config = TaskConfig::new().name("sampler").stack_size(8192).core(Some(Core::Core1));
+    ///
+    /// let handle = task::spawn(&config, || sample(1000))?;
+    /// let samples = handle.join();
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn spawn<F, T>(config: &TaskConfig, f: F) -> Result<JoinHandle<T>, EspError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let packet = Arc::new(Packet {
+            result: crate::mutex::Mutex::new(None),
+            done: crate::mutex::Condvar::new(),
+        });
+
+        let task_packet = packet.clone();
+
+        let main: Box<Box<dyn FnOnce() + Send>> = Box::new(Box::new(move || {
+            let result = f();
+
+            *task_packet.result.lock() = Some(result);
+            task_packet.done.notify_all();
+        }));
+
+        let name = config.c_name();
+
+        let main = Box::into_raw(main);
+        let mut task: TaskHandle_t = ptr::null_mut();
+
+        let created = unsafe {
+            xTaskCreatePinnedToCore(
+                Some(task_main),
+                name.as_ptr() as *const _,
+                config.stack_size as _,
+                main as *mut _,
+                config.priority,
+                &mut task,
+                config.core.map_or(tskNO_AFFINITY as _, |core| core as _),
+            )
+        };
+
+        if created != 1 {
+            drop(unsafe { Box::from_raw(main) });
+
+            return Err(EspError::from(ESP_ERR_NO_MEM as i32).unwrap());
+        }
+
+        Ok(JoinHandle { task, packet })
+    }
+
+    #[cfg(feature = "alloc")]
+    extern "C" fn task_main(arg: *mut c_types::c_void) {
+        let main = unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce() + Send>) };
+
+        main();
+
+        unsafe { vTaskDelete(ptr::null_mut()) };
+    }
+
+    /// The result of a spawned task, set once the task is about to be deleted
+    #[cfg(feature = "alloc")]
+    struct Packet<T> {
+        result: crate::mutex::Mutex<Option<T>>,
+        done: crate::mutex::Condvar,
+    }
+
+    /// Handle of a task created by [`spawn`]
+    ///
+    /// Dropping the handle detaches the task.
+    #[cfg(feature = "alloc")]
+    pub struct JoinHandle<T> {
+        task: TaskHandle_t,
+        packet: Arc<Packet<T>>,
+    }
+
+    #[cfg(feature = "alloc")]
+    impl<T> JoinHandle<T> {
+        /// Wait for the task to finish and return its result
+        pub fn join(self) -> T {
+            let mut result = self.packet.result.lock();
+
+            loop {
+                if let Some(result) = result.take() {
+                    return result;
+                }
+
+                result = self.packet.done.wait(result);
+            }
+        }
+
+        pub fn is_finished(&self) -> bool {
+            self.packet.result.lock().is_some()
+        }
+
+        /// Priority of the task, or `None` if it has finished
+        pub fn priority(&self) -> Option<u32> {
+            self.with_task(|task| unsafe { uxTaskPriorityGet(task) })
+        }
+
+        /// Change the priority of the task; returns false if it has finished
+        pub fn set_priority(&self, priority: u32) -> bool {
+            self.with_task(|task| unsafe { vTaskPrioritySet(task, priority) })
+                .is_some()
+        }
+
+        /// The minimum amount of stack (in bytes) which has remained free for the task
+        /// since it started, or `None` if it has finished
+        pub fn stack_high_water_mark(&self) -> Option<usize> {
+            self.with_task(|task| unsafe { uxTaskGetStackHighWaterMark(task) as _ })
+        }
+
+        /// Call `f` with the handle of the task unless it has finished; holding the lock
+        /// on the result keeps the task from finishing and being deleted meanwhile
+        fn with_task<R>(&self, f: impl FnOnce(TaskHandle_t) -> R) -> Option<R> {
+            let result = self.packet.result.lock();
+
+            if result.is_some() {
+                None
+            } else {
+                Some(f(self.task))
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    unsafe impl<T: Send> Send for JoinHandle<T> {}
+    #[cfg(feature = "alloc")]
+    unsafe impl<T: Send> Sync for JoinHandle<T> {}
+
+    /// A statically allocated task which calls a step function over and over, used by
+    /// drivers which have to forward the events of an ESP-IDF driver offering no ISR hooks
+    ///
+    /// Each step should block for a bounded time, e.g. with [`Pump::wait`]; that time is
+    /// how long [`Pump::stop`] may have to wait for the task to notice it should stop.
+    pub(crate) struct Pump {
+        running: AtomicBool,
+        stop: AtomicBool,
+        stopped: AtomicBool,
+        task: AtomicPtr<c_types::c_void>,
+        step: AtomicPtr<()>,
+    }
+
+    impl Pump {
+        pub const fn new() -> Self {
+            Self {
+                running: AtomicBool::new(false),
+                stop: AtomicBool::new(false),
+                stopped: AtomicBool::new(false),
+                task: AtomicPtr::new(ptr::null_mut()),
+                step: AtomicPtr::new(ptr::null_mut()),
+            }
+        }
+
+        pub fn is_running(&self) -> bool {
+            self.running.load(Ordering::SeqCst)
+        }
+
+        /// Create the task according to `config`, unless it is running already
+        ///
+        /// Returns false if the task was running already.
+        pub fn start(&'static self, config: &TaskConfig, step: fn()) -> Result<bool, EspError> {
+            if self
+                .running
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                return Ok(false);
+            }
+
+            self.stop.store(false, Ordering::SeqCst);
+            self.stopped.store(false, Ordering::SeqCst);
+            self.step.store(step as *mut (), Ordering::SeqCst);
+
+            let name = config.c_name();
+            let mut task: TaskHandle_t = ptr::null_mut();
+
+            let created = unsafe {
+                xTaskCreatePinnedToCore(
+                    Some(Self::main),
+                    name.as_ptr() as *const _,
+                    config.stack_size as _,
+                    self as *const Self as *mut _,
+                    config.priority,
+                    &mut task,
+                    config.core.map_or(tskNO_AFFINITY as _, |core| core as _),
+                )
+            };
+
+            if created != 1 {
+                self.running.store(false, Ordering::SeqCst);
+
+                return Err(EspError::from(ESP_ERR_NO_MEM as i32).unwrap());
+            }
+
+            self.task.store(task as _, Ordering::SeqCst);
+
+            Ok(true)
+        }
+
+        /// Make the task return from its current [`Pump::wait`] early
+        pub fn wake(&self) {
+            let task = self.task.load(Ordering::SeqCst);
+
+            if !task.is_null() {
+                unsafe { notify(task as _, 1) };
+            }
+        }
+
+        /// Block the calling step for up to `timeout`, or until [`Pump::wake`] is called
+        pub fn wait(&self, timeout: Duration) {
+            wait_notification(Some(timeout));
+        }
+
+        /// Stop the task and wait until it has returned from its current step
+        ///
+        /// Returns false if the task was not running. Must not be called from the task itself.
+        pub fn stop(&self) -> bool {
+            if !self.running.swap(false, Ordering::SeqCst) {
+                return false;
+            }
+
+            self.stop.store(true, Ordering::SeqCst);
+            self.wake();
+
+            while !self.stopped.load(Ordering::SeqCst) {
+                unsafe { vTaskDelay(1) };
+            }
+
+            // The task is deleted here rather than by itself, so that `wake` never
+            // notifies a deleted task
+            let task = self.task.swap(ptr::null_mut(), Ordering::SeqCst);
+
+            unsafe { vTaskDelete(task as _) };
+
+            true
+        }
+
+        extern "C" fn main(arg: *mut c_types::c_void) {
+            let pump = unsafe { &*(arg as *const Self) };
+            let step: fn() = unsafe { core::mem::transmute(pump.step.load(Ordering::SeqCst)) };
+
+            while !pump.stop.load(Ordering::SeqCst) {
+                step();
+            }
+
+            pump.stopped.store(true, Ordering::SeqCst);
+
+            loop {
+                unsafe { vTaskSuspend(ptr::null_mut()) };
+            }
+        }
+    }
+}
+
+pub mod asynch {
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::{Context, Poll, Waker};
+
+    /// A notification which can be signalled from an ISR or a FreeRTOS task
+    /// and awaited from an async executor
+    ///
+    /// A notification signalled while nobody is waiting is remembered, so the
+    /// next wait completes immediately. Waiters should therefore re-check the
+    /// condition they are waiting for after the wait completes.
+    ///
+    /// When signalled from an ISR, the waker of the waiter is invoked from that ISR
+    /// as well, so the wakers of the executor must be ISR-safe: they must neither
+    /// block nor allocate (e.g. by only setting a flag or notifying a FreeRTOS task).
+    pub struct HalIsrNotification {
+        waker: super::Mutex<Option<Waker>>,
+        notified: AtomicBool,
+    }
+
+    impl HalIsrNotification {
+        pub const fn new() -> Self {
+            Self {
+                waker: super::Mutex::new(None),
+                notified: AtomicBool::new(false),
+            }
+        }
+
+        /// Clears any pending notification
+        pub fn reset(&self) {
+            self.notified.store(false, Ordering::SeqCst);
+        }
+
+        /// Signals the notification, waking up the registered waiter (if any)
+        ///
+        /// Returns true if a waiter was woken up.
+        ///
+        /// The waker is invoked directly from the caller's context, which may be an ISR.
+        #[link_section = ".iram1.interrupt_asynch_notify"]
+        pub fn notify(&self) -> bool {
+            self.notified.store(true, Ordering::SeqCst);
+
+            let waker = self.waker.lock().take();
+
+            if let Some(waker) = waker {
+                waker.wake();
+
+                true
+            } else {
+                false
+            }
+        }
+
+        /// Polls the notification, registering the waker of `cx` if it is not signalled yet
+        pub fn poll_wait(&self, cx: &Context<'_>) -> Poll<()> {
+            *self.waker.lock() = Some(cx.waker().clone());
+
+            if self.notified.swap(false, Ordering::SeqCst) {
+                self.waker.lock().take();
+
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+
+        /// Waits for the notification to be signalled
+        pub fn wait(&self) -> HalIsrNotificationWait<'_> {
+            HalIsrNotificationWait(self)
+        }
+    }
+
+    impl Default for HalIsrNotification {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    pub struct HalIsrNotificationWait<'a>(&'a HalIsrNotification);
+
+    impl<'a> Future for HalIsrNotificationWait<'a> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.0.poll_wait(cx)
+        }
+    }
 }
 
 /// A critical section allows the user to disable interrupts
@@ -30,6 +30,8 @@ pub mod can;
 pub mod cpu;
 #[cfg(not(feature = "riscv-ulp-hal"))]
 pub mod delay;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub mod esp_timer;
 pub mod gpio;
 #[cfg(esp32)]
 pub mod hall;
@@ -48,6 +50,10 @@ pub mod rmt;
 pub mod serial;
 #[cfg(not(feature = "riscv-ulp-hal"))]
 pub mod spi;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub mod temp_sensor;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+pub mod timer;
 #[cfg(all(any(esp32, esp32s2, esp32s3), not(feature = "riscv-ulp-hal")))]
 pub mod ulp;
 pub mod units;
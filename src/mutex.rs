@@ -23,6 +23,15 @@ impl<T> Mutex<T> {
     pub fn lock(&self) -> MutexGuard<'_, T> {
         MutexGuard::new(self)
     }
+
+    pub fn into_inner(self) -> T {
+        let mut this = core::mem::ManuallyDrop::new(self);
+
+        let r = unsafe { pthread_mutex_destroy(this.0.get_mut() as *mut _) };
+        debug_assert_eq!(r, 0);
+
+        unsafe { ptr::read(this.1.get()) }
+    }
 }
 
 impl<T> Drop for Mutex<T> {
@@ -8,8 +8,6 @@ use crate::adc;
 #[cfg(not(feature = "riscv-ulp-hal"))]
 use crate::can;
 use crate::gpio;
-#[cfg(esp32)]
-use crate::hall;
 #[cfg(not(feature = "riscv-ulp-hal"))]
 use crate::i2c;
 #[cfg(not(feature = "riscv-ulp-hal"))]
@@ -20,6 +18,10 @@ use crate::rmt;
 use crate::serial;
 #[cfg(not(feature = "riscv-ulp-hal"))]
 use crate::spi;
+#[cfg(all(any(esp32s2, esp32s3, esp32c3), not(feature = "riscv-ulp-hal")))]
+use crate::temp_sensor;
+#[cfg(not(feature = "riscv-ulp-hal"))]
+use crate::timer;
 #[cfg(all(
     any(esp32, esp32s2, esp32s3),
     not(feature = "riscv-ulp-hal"),
@@ -33,7 +35,7 @@ pub struct Peripherals {
     pub uart0: serial::UART0,
     #[cfg(not(feature = "riscv-ulp-hal"))]
     pub uart1: serial::UART1,
-    #[cfg(all(esp32, not(feature = "riscv-ulp-hal")))]
+    #[cfg(all(any(esp32, esp32s3), not(feature = "riscv-ulp-hal")))]
     pub uart2: serial::UART2,
     #[cfg(not(feature = "riscv-ulp-hal"))]
     pub i2c0: i2c::I2C0,
@@ -47,14 +49,22 @@ pub struct Peripherals {
     pub spi3: spi::SPI3,
     pub adc1: adc::ADC1,
     pub adc2: adc::ADC2,
-    #[cfg(esp32)]
-    pub hall_sensor: hall::HallSensor,
+    #[cfg(all(any(esp32s2, esp32s3, esp32c3), not(feature = "riscv-ulp-hal")))]
+    pub temp_sensor: temp_sensor::TempSensor,
     #[cfg(not(feature = "riscv-ulp-hal"))]
     pub can: can::CAN,
     #[cfg(not(feature = "riscv-ulp-hal"))]
     pub ledc: ledc::Peripheral,
     #[cfg(not(feature = "riscv-ulp-hal"))]
     pub rmt: rmt::Peripheral,
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub timer00: timer::TIMER00,
+    #[cfg(all(not(esp32c3), not(feature = "riscv-ulp-hal")))]
+    pub timer01: timer::TIMER01,
+    #[cfg(not(feature = "riscv-ulp-hal"))]
+    pub timer10: timer::TIMER10,
+    #[cfg(all(not(esp32c3), not(feature = "riscv-ulp-hal")))]
+    pub timer11: timer::TIMER11,
     #[cfg(all(
         any(esp32, esp32s2, esp32s3),
         not(feature = "riscv-ulp-hal"),
@@ -87,7 +97,7 @@ impl Peripherals {
             uart0: serial::UART0::new(),
             #[cfg(not(feature = "riscv-ulp-hal"))]
             uart1: serial::UART1::new(),
-            #[cfg(all(esp32, not(feature = "riscv-ulp-hal")))]
+            #[cfg(all(any(esp32, esp32s3), not(feature = "riscv-ulp-hal")))]
             uart2: serial::UART2::new(),
             #[cfg(not(feature = "riscv-ulp-hal"))]
             i2c0: i2c::I2C0::new(),
@@ -101,14 +111,22 @@ impl Peripherals {
             spi3: spi::SPI3::new(),
             adc1: adc::ADC1::new(),
             adc2: adc::ADC2::new(),
-            #[cfg(esp32)]
-            hall_sensor: hall::HallSensor::new(),
+            #[cfg(all(any(esp32s2, esp32s3, esp32c3), not(feature = "riscv-ulp-hal")))]
+            temp_sensor: temp_sensor::TempSensor::new(),
             #[cfg(not(feature = "riscv-ulp-hal"))]
             can: can::CAN::new(),
             #[cfg(not(feature = "riscv-ulp-hal"))]
             ledc: ledc::Peripheral::new(),
             #[cfg(not(feature = "riscv-ulp-hal"))]
             rmt: rmt::Peripheral::new(),
+            #[cfg(not(feature = "riscv-ulp-hal"))]
+            timer00: timer::TIMER00::new(),
+            #[cfg(all(not(esp32c3), not(feature = "riscv-ulp-hal")))]
+            timer01: timer::TIMER01::new(),
+            #[cfg(not(feature = "riscv-ulp-hal"))]
+            timer10: timer::TIMER10::new(),
+            #[cfg(all(not(esp32c3), not(feature = "riscv-ulp-hal")))]
+            timer11: timer::TIMER11::new(),
             #[cfg(all(
                 any(esp32, esp32s2, esp32s3),
                 not(feature = "riscv-ulp-hal"),
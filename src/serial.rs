@@ -1,6 +1,6 @@
 //! UART peripheral control
 //!
-//! Controls UART peripherals (UART0, UART1, UART2).
+//! Controls UART peripherals (UART0, UART1 and - on ESP32 and ESP32-S3 - UART2).
 //! Notice that UART0 is typically already used for loading firmware and logging.
 //! Therefore use UART1 and UART2 in your application.
 //! Any pin can be used for `rx` and `tx`.
@@ -35,32 +35,73 @@
 //! ```
 //!
 //! # TODO
-//! - Add all extra features esp32 supports (eg rs485, etc. etc.)
 //! - Free APB lock when TX is idle (and no RX used)
 //! - Address errata 3.17: UART fifo_cnt is inconsistent with FIFO pointer
 
 use core::marker::PhantomData;
 use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use core::time::Duration;
 
+use crate::delay::TickType;
+use crate::esp_timer;
 use crate::gpio::*;
+use crate::interrupt::asynch::HalIsrNotification;
 use crate::units::*;
 
 use esp_idf_sys::*;
 
-const UART_FIFO_SIZE: i32 = 128;
+const UART_FIFO_SIZE: usize = 128;
+
+/// Number of characters an async writer waits to be sent when the hardware TX FIFO is full
+const ASYNC_WRITE_WAIT_CHARS: usize = UART_FIFO_SIZE / 2;
+
+/// Number of characters an async flush waits to be sent before checking again whether TX is done
+const ASYNC_FLUSH_WAIT_CHARS: usize = UART_FIFO_SIZE / 4;
+
+/// UART driver event, as reported through the event queue
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Event {
+    /// New data has been received; contains the number of bytes available
+    Data(usize),
+    /// A break condition has been detected
+    Break,
+    /// The RX ring buffer is full
+    BufferFull,
+    /// The hardware RX FIFO has overflowed
+    FifoOverflow,
+    /// A frame error has been detected
+    FrameErr,
+    /// A parity error has been detected
+    ParityErr,
+    /// Data has been sent, followed by a break signal
+    DataBreak,
+    /// The configured RX pattern has been detected
+    PatternDetected,
+    /// Any other event reported by the driver
+    Other,
+}
 
-// /// Interrupt event
-// pub enum Event {
-//     /// New data has been received
-//     Rxne,
-//     /// New data can be sent
-//     Txe,
-//     /// Idle line state detected
-//     Idle,
-// }
+impl From<uart_event_t> for Event {
+    #[allow(non_upper_case_globals)]
+    fn from(event: uart_event_t) -> Self {
+        match event.type_ {
+            uart_event_type_t_UART_DATA => Event::Data(event.size as usize),
+            uart_event_type_t_UART_BREAK => Event::Break,
+            uart_event_type_t_UART_BUFFER_FULL => Event::BufferFull,
+            uart_event_type_t_UART_FIFO_OVF => Event::FifoOverflow,
+            uart_event_type_t_UART_FRAME_ERR => Event::FrameErr,
+            uart_event_type_t_UART_PARITY_ERR => Event::ParityErr,
+            uart_event_type_t_UART_DATA_BREAK => Event::DataBreak,
+            uart_event_type_t_UART_PATTERN_DET => Event::PatternDetected,
+            _ => Event::Other,
+        }
+    }
+}
 
 /// UART configuration
 pub mod config {
+    use crate::interrupt::InterruptFlags;
     use crate::units::*;
     use esp_idf_sys::*;
 
@@ -97,7 +138,7 @@ pub mod config {
         }
     }
 
-    /// Number of data bits
+    /// Flow control
     #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
     pub enum FlowControl {
         None,
@@ -105,6 +146,14 @@ pub mod config {
         CTS,
         CTSRTS,
         MAX,
+        /// Software (XON/XOFF) flow control
+        ///
+        /// XOFF is sent when the hardware RX FIFO holds more than `xoff_threshold` bytes,
+        /// and XON once it holds less than `xon_threshold` bytes.
+        XonXoff {
+            xon_threshold: u8,
+            xoff_threshold: u8,
+        },
     }
 
     impl From<FlowControl> for uart_hw_flowcontrol_t {
@@ -115,6 +164,7 @@ pub mod config {
                 FlowControl::CTS => uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS,
                 FlowControl::CTSRTS => uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS,
                 FlowControl::MAX => uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_MAX,
+                FlowControl::XonXoff { .. } => uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_DISABLE,
             }
         }
     }
@@ -196,6 +246,89 @@ pub mod config {
         }
     }
 
+    /// UART communication mode
+    #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+    pub enum Mode {
+        /// Regular UART mode
+        UART,
+        /// RS-485 half-duplex mode with automatic RTS-driven transceiver direction control
+        RS485HalfDuplex,
+        /// RS-485 half-duplex mode with collision detection
+        RS485CollisionDetect,
+        /// RS-485 mode where the application drives the RTS pin (transceiver direction) itself
+        RS485AppControl,
+        /// IrDA mode
+        IrDA,
+    }
+
+    impl From<Mode> for uart_mode_t {
+        fn from(mode: Mode) -> Self {
+            match mode {
+                Mode::UART => uart_mode_t_UART_MODE_UART,
+                Mode::RS485HalfDuplex => uart_mode_t_UART_MODE_RS485_HALF_DUPLEX,
+                Mode::RS485CollisionDetect => uart_mode_t_UART_MODE_RS485_COLLISION_DETECT,
+                Mode::RS485AppControl => uart_mode_t_UART_MODE_RS485_APP_CTRL,
+                Mode::IrDA => uart_mode_t_UART_MODE_IRDA,
+            }
+        }
+    }
+
+    /// RX pattern detection configuration
+    ///
+    /// All times are expressed in baud-rate cycles.
+    #[derive(Debug, Copy, Clone)]
+    pub struct PatternDetection {
+        /// Maximum gap between two pattern characters
+        pub gap_timeout: u16,
+        /// Minimum idle time after the last pattern character
+        pub post_idle: u16,
+        /// Minimum idle time before the first pattern character
+        pub pre_idle: u16,
+        /// Maximum number of detected pattern positions kept by the driver
+        pub queue_length: usize,
+    }
+
+    impl PatternDetection {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn gap_timeout(mut self, gap_timeout: u16) -> Self {
+            self.gap_timeout = gap_timeout;
+            self
+        }
+
+        #[must_use]
+        pub fn post_idle(mut self, post_idle: u16) -> Self {
+            self.post_idle = post_idle;
+            self
+        }
+
+        #[must_use]
+        pub fn pre_idle(mut self, pre_idle: u16) -> Self {
+            self.pre_idle = pre_idle;
+            self
+        }
+
+        #[must_use]
+        pub fn queue_length(mut self, queue_length: usize) -> Self {
+            self.queue_length = queue_length;
+            self
+        }
+    }
+
+    impl Default for PatternDetection {
+        fn default() -> Self {
+            Self {
+                gap_timeout: 9,
+                post_idle: 0,
+                pre_idle: 0,
+                queue_length: 20,
+            }
+        }
+    }
+
     /// UART configuration
     #[derive(Debug, Copy, Clone)]
     pub struct Config {
@@ -204,6 +337,18 @@ pub mod config {
         pub parity: Parity,
         pub stop_bits: StopBits,
         pub flow_control: FlowControl,
+        pub mode: Mode,
+        pub event_queue_size: usize,
+        pub rx_buffer_size: usize,
+        pub tx_buffer_size: usize,
+        pub rx_timeout: Option<u8>,
+        pub rx_fifo_full_threshold: Option<u16>,
+        pub tx_fifo_empty_threshold: Option<u16>,
+        pub invert_tx: bool,
+        pub invert_rx: bool,
+        pub invert_rts: bool,
+        pub invert_cts: bool,
+        pub intr_flags: InterruptFlags,
     }
 
     impl Config {
@@ -252,6 +397,114 @@ pub mod config {
             self.flow_control = flow_control;
             self
         }
+
+        /// Communication mode
+        ///
+        /// Note that the RS-485 modes drive the transceiver direction with the RTS pin,
+        /// so it must be configured, and [`super::Serial::new`] rejects them in combination
+        /// with hardware flow control.
+        #[must_use]
+        pub fn mode(mut self, mode: Mode) -> Self {
+            self.mode = mode;
+            self
+        }
+
+        /// Size of the driver event queue; `0` disables event reporting
+        #[must_use]
+        pub fn event_queue_size(mut self, event_queue_size: usize) -> Self {
+            self.event_queue_size = event_queue_size;
+            self
+        }
+
+        /// Size of the driver RX ring buffer; must be larger than the hardware FIFO
+        #[must_use]
+        pub fn rx_buffer_size(mut self, rx_buffer_size: usize) -> Self {
+            self.rx_buffer_size = rx_buffer_size;
+            self
+        }
+
+        /// Size of the driver TX ring buffer; `0` makes writes block until
+        /// all data is placed in the hardware FIFO
+        #[must_use]
+        pub fn tx_buffer_size(mut self, tx_buffer_size: usize) -> Self {
+            self.tx_buffer_size = tx_buffer_size;
+            self
+        }
+
+        /// RX timeout, in UART symbol times, after which the received data is
+        /// moved from the hardware FIFO to the RX ring buffer
+        #[must_use]
+        pub fn rx_timeout(mut self, rx_timeout: Option<u8>) -> Self {
+            self.rx_timeout = rx_timeout;
+            self
+        }
+
+        /// Number of bytes in the hardware RX FIFO which triggers moving them to the RX ring buffer
+        #[must_use]
+        pub fn rx_fifo_full_threshold(mut self, threshold: Option<u16>) -> Self {
+            self.rx_fifo_full_threshold = threshold;
+            self
+        }
+
+        /// Number of bytes in the hardware TX FIFO below which it is refilled from the TX ring buffer
+        #[must_use]
+        pub fn tx_fifo_empty_threshold(mut self, threshold: Option<u16>) -> Self {
+            self.tx_fifo_empty_threshold = threshold;
+            self
+        }
+
+        #[must_use]
+        pub fn invert_tx(mut self, invert: bool) -> Self {
+            self.invert_tx = invert;
+            self
+        }
+
+        #[must_use]
+        pub fn invert_rx(mut self, invert: bool) -> Self {
+            self.invert_rx = invert;
+            self
+        }
+
+        #[must_use]
+        pub fn invert_rts(mut self, invert: bool) -> Self {
+            self.invert_rts = invert;
+            self
+        }
+
+        #[must_use]
+        pub fn invert_cts(mut self, invert: bool) -> Self {
+            self.invert_cts = invert;
+            self
+        }
+
+        /// Flags for the allocation of the driver interrupt
+        #[must_use]
+        pub fn intr_flags(mut self, flags: InterruptFlags) -> Self {
+            self.intr_flags = flags;
+            self
+        }
+
+        pub(super) fn line_inverse_mask(&self) -> u32 {
+            let mut mask = uart_signal_inv_t_UART_SIGNAL_INV_DISABLE;
+
+            if self.invert_tx {
+                mask |= uart_signal_inv_t_UART_SIGNAL_TXD_INV;
+            }
+
+            if self.invert_rx {
+                mask |= uart_signal_inv_t_UART_SIGNAL_RXD_INV;
+            }
+
+            if self.invert_rts {
+                mask |= uart_signal_inv_t_UART_SIGNAL_RTS_INV;
+            }
+
+            if self.invert_cts {
+                mask |= uart_signal_inv_t_UART_SIGNAL_CTS_INV;
+            }
+
+            mask as _
+        }
     }
 
     impl Default for Config {
@@ -262,6 +515,18 @@ pub mod config {
                 parity: Parity::ParityNone,
                 stop_bits: StopBits::STOP1,
                 flow_control: FlowControl::None,
+                mode: Mode::UART,
+                event_queue_size: 0,
+                rx_buffer_size: super::UART_FIFO_SIZE * 2,
+                tx_buffer_size: super::UART_FIFO_SIZE * 2,
+                rx_timeout: None,
+                rx_fifo_full_threshold: None,
+                tx_fifo_empty_threshold: None,
+                invert_tx: false,
+                invert_rx: false,
+                invert_rts: false,
+                invert_cts: false,
+                intr_flags: Default::default(),
             }
         }
     }
@@ -312,8 +577,11 @@ pub struct Serial<
 /// Serial receiver
 pub struct Rx<UART: Uart> {
     _uart: PhantomData<UART>,
+    event_queue: QueueHandle_t,
 }
 
+unsafe impl<UART: Uart> Send for Rx<UART> {}
+
 /// Serial transmitter
 pub struct Tx<UART: Uart> {
     _uart: PhantomData<UART>,
@@ -328,6 +596,26 @@ impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
         pins: Pins<TX, RX, CTS, RTS>,
         config: config::Config,
     ) -> Result<Self, EspError> {
+        Self::validate_pins(&pins)?;
+        config.intr_flags.validate()?;
+
+        // The RS-485 modes use the RTS pin for the transceiver direction
+        let rs485 = matches!(
+            config.mode,
+            config::Mode::RS485HalfDuplex
+                | config::Mode::RS485CollisionDetect
+                | config::Mode::RS485AppControl
+        );
+
+        let hw_flow_control = !matches!(
+            config.flow_control,
+            config::FlowControl::None | config::FlowControl::XonXoff { .. }
+        );
+
+        if rs485 && hw_flow_control {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
         let uart_config = uart_config_t {
             baud_rate: config.baudrate.0 as i32,
             data_bits: config.data_bits.into(),
@@ -339,6 +627,18 @@ impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
 
         esp!(unsafe { uart_param_config(UART::port(), &uart_config) })?;
 
+        if let config::FlowControl::XonXoff {
+            xon_threshold,
+            xoff_threshold,
+        } = config.flow_control
+        {
+            esp!(unsafe {
+                uart_set_sw_flow_ctrl(UART::port(), true, xon_threshold, xoff_threshold)
+            })?;
+        }
+
+        esp!(unsafe { uart_set_line_inverse(UART::port(), config.line_inverse_mask()) })?;
+
         esp!(unsafe {
             uart_set_pin(
                 UART::port(),
@@ -349,23 +649,131 @@ impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
             )
         })?;
 
+        let mut event_queue: QueueHandle_t = ptr::null_mut();
+
         esp!(unsafe {
             uart_driver_install(
                 UART::port(),
-                UART_FIFO_SIZE * 2,
-                UART_FIFO_SIZE * 2,
-                0,
-                ptr::null_mut(),
-                0,
+                config.rx_buffer_size as _,
+                config.tx_buffer_size as _,
+                config.event_queue_size as _,
+                if config.event_queue_size > 0 {
+                    &mut event_queue
+                } else {
+                    ptr::null_mut()
+                },
+                config.intr_flags.into(),
             )
         })?;
 
-        Ok(Self {
+        esp!(unsafe { uart_set_mode(UART::port(), config.mode.into()) })?;
+
+        let mut serial = Self {
             uart,
             pins,
-            rx: Rx { _uart: PhantomData },
+            rx: Rx {
+                _uart: PhantomData,
+                event_queue,
+            },
             tx: Tx { _uart: PhantomData },
-        })
+        };
+
+        if let Some(rx_timeout) = config.rx_timeout {
+            serial.set_rx_timeout(rx_timeout)?;
+        }
+
+        if let Some(threshold) = config.rx_fifo_full_threshold {
+            serial.set_rx_fifo_full_threshold(threshold)?;
+        }
+
+        if let Some(threshold) = config.tx_fifo_empty_threshold {
+            serial.set_tx_fifo_empty_threshold(threshold)?;
+        }
+
+        Ok(serial)
+    }
+
+    /// Change the communication mode
+    pub fn change_mode(&mut self, mode: config::Mode) -> Result<&mut Self, EspError> {
+        esp_result!(unsafe { uart_set_mode(UART::port(), mode.into()) }, self)
+    }
+
+    /// Returns true if a collision has been detected on the RS-485 bus
+    /// during the last transmission
+    ///
+    /// Only meaningful in the RS-485 modes; the flag is cleared by the driver
+    /// when a new transmission starts.
+    pub fn collision_detected(&self) -> Result<bool, EspError> {
+        let mut collision = false;
+        esp_result!(
+            unsafe { uart_get_collision_flag(UART::port(), &mut collision) },
+            collision
+        )
+    }
+
+    fn validate_pins(pins: &Pins<TX, RX, CTS, RTS>) -> Result<(), EspError> {
+        let valid = is_valid_output_pin(pins.tx.pin())
+            && is_valid_pin(pins.rx.pin())
+            && pins.cts.as_ref().map_or(true, |p| is_valid_pin(p.pin()))
+            && pins
+                .rts
+                .as_ref()
+                .map_or(true, |p| is_valid_output_pin(p.pin()));
+
+        if valid {
+            Ok(())
+        } else {
+            Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap())
+        }
+    }
+
+    /// Create a new serial driver supporting async reads and writes
+    ///
+    /// Async readers are woken up from the UART interrupt as soon as data arrives. Async
+    /// writes go directly to the hardware TX FIFO, so the TX ring buffer is disabled to keep
+    /// them in order with blocking writes. A writer finding the FIFO full is woken up by an
+    /// `esp_timer` once enough characters have been sent at the current line settings.
+    ///
+    /// The interrupt notifies the readers through the hook also used by `select()` of the
+    /// UART VFS driver, so `select()` must not be used on the same port.
+    pub fn new_async(
+        uart: UART,
+        pins: Pins<TX, RX, CTS, RTS>,
+        config: config::Config,
+    ) -> Result<Self, EspError> {
+        let serial = Self::new(uart, pins, config.tx_buffer_size(0))?;
+
+        if let Err(err) = AsyncState::start(UART::port()) {
+            esp!(unsafe { uart_driver_delete(UART::port()) })?;
+
+            return Err(err);
+        }
+
+        Ok(serial)
+    }
+
+    /// Change the RX timeout, in UART symbol times
+    pub fn set_rx_timeout(&mut self, rx_timeout: u8) -> Result<&mut Self, EspError> {
+        esp_result!(
+            unsafe { uart_set_rx_timeout(UART::port(), rx_timeout) },
+            self
+        )
+    }
+
+    /// Change the hardware RX FIFO full threshold
+    pub fn set_rx_fifo_full_threshold(&mut self, threshold: u16) -> Result<&mut Self, EspError> {
+        esp_result!(
+            unsafe { uart_set_rx_full_threshold(UART::port(), threshold as _) },
+            self
+        )
+    }
+
+    /// Change the hardware TX FIFO empty threshold
+    pub fn set_tx_fifo_empty_threshold(&mut self, threshold: u16) -> Result<&mut Self, EspError> {
+        esp_result!(
+            unsafe { uart_set_tx_empty_threshold(UART::port(), threshold as _) },
+            self
+        )
     }
 
     /// Change the number of stop bits
@@ -450,15 +858,74 @@ impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
         )
     }
 
-    // /// Starts listening for an interrupt event
-    // pub fn listen(&mut self, _event: Event) {
-    //     unimplemented!();
-    // }
+    /// Change the flow control
+    ///
+    /// Switching between hardware and software (XON/XOFF) flow control disables the
+    /// other one. Hardware flow control must not be used in the RS-485 modes.
+    pub fn change_flow_control(
+        &mut self,
+        flow_control: config::FlowControl,
+    ) -> Result<&mut Self, EspError> {
+        match flow_control {
+            config::FlowControl::XonXoff {
+                xon_threshold,
+                xoff_threshold,
+            } => {
+                esp!(unsafe { uart_set_hw_flow_ctrl(UART::port(), flow_control.into(), 0) })?;
+                esp!(unsafe {
+                    uart_set_sw_flow_ctrl(UART::port(), true, xon_threshold, xoff_threshold)
+                })?;
+            }
+            _ => {
+                esp!(unsafe { uart_set_sw_flow_ctrl(UART::port(), false, 0, 0) })?;
+                esp!(unsafe { uart_set_hw_flow_ctrl(UART::port(), flow_control.into(), 0) })?;
+            }
+        }
 
-    // /// Stop listening for an interrupt event
-    // pub fn unlisten(&mut self, _event: Event) {
-    //     unimplemented!();
-    // }
+        Ok(self)
+    }
+
+    /// Waits for the next driver event for up to `timeout` (`None` waits forever)
+    ///
+    /// Returns `Ok(None)` if no event arrived in time.
+    /// Requires a non-zero `event_queue_size` in the configuration.
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, EspError> {
+        self.rx.next_event(timeout)
+    }
+
+    /// Enables detection of `count` consecutive `pattern` characters in the received data
+    ///
+    /// Useful for framing line-based protocols, e.g. NMEA sentences (`b'\n'`) or
+    /// AT command escapes (`b'+'` three times). Every detection is reported as
+    /// [`Event::PatternDetected`] and its position in the RX buffer can then be
+    /// retrieved with [`Rx::pattern_position`].
+    pub fn enable_pattern_detect(
+        &mut self,
+        pattern: u8,
+        count: u8,
+        config: config::PatternDetection,
+    ) -> Result<&mut Self, EspError> {
+        esp!(unsafe {
+            uart_enable_pattern_det_baud_intr(
+                UART::port(),
+                pattern as _,
+                count,
+                config.gap_timeout as _,
+                config.post_idle as _,
+                config.pre_idle as _,
+            )
+        })?;
+
+        esp_result!(
+            unsafe { uart_pattern_queue_reset(UART::port(), config.queue_length as _) },
+            self
+        )
+    }
+
+    /// Disables RX pattern detection
+    pub fn disable_pattern_detect(&mut self) -> Result<&mut Self, EspError> {
+        esp_result!(unsafe { uart_disable_pattern_det_intr(UART::port()) }, self)
+    }
 
     // /// Return true if the receiver is idle
     // pub fn is_rx_idle(&self) -> bool {
@@ -478,28 +945,36 @@ impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
     /// Release the UART and GPIO resources
     #[allow(clippy::type_complexity)]
     pub fn release(self) -> Result<(UART, Pins<TX, RX, CTS, RTS>), EspError> {
+        let stopped = AsyncState::stop(UART::port());
+
         esp!(unsafe { uart_driver_delete(UART::port()) })?;
 
+        stopped?;
+
         // self.pins.tx.reset()?;
         // self.pins.rx.reset()?;
 
         Ok((self.uart, self.pins))
     }
 
-    // pub fn reset_rx_fifo(&mut self) {
-    //     // Hardware issue: rxfifo_rst does not work properly;
-    //     while self.uart.status.read().rxfifo_cnt().bits() != 0
-    //         || (self.uart.mem_rx_status.read().mem_rx_rd_addr().bits()
-    //             != self.uart.mem_rx_status.read().mem_rx_wr_addr().bits())
-    //     {
-    //         self.rx.read().unwrap();
-    //     }
-    // }
+    /// Discard all data in the RX ring buffer and the hardware RX FIFO
+    pub fn reset_rx_fifo(&mut self) -> Result<&mut Self, EspError> {
+        self.rx.clear()?;
 
-    // pub fn reset_tx_fifo(&self) {
-    //     self.uart.conf0.write(|w| w.txfifo_rst().set_bit());
-    //     self.uart.conf0.write(|w| w.txfifo_rst().clear_bit());
-    // }
+        Ok(self)
+    }
+
+    /// Empty the TX ring buffer and the hardware TX FIFO, waiting for up to `timeout`
+    /// (`None` waits forever) until all their data has been sent
+    ///
+    /// Queued TX data cannot be discarded, as ESP-IDF offers no way to do so, so the
+    /// buffers are emptied by draining them. Fails with `ESP_ERR_TIMEOUT` if they did not
+    /// drain in time.
+    pub fn reset_tx_fifo(&mut self, timeout: Option<Duration>) -> Result<&mut Self, EspError> {
+        self.tx.flush_tx(timeout)?;
+
+        Ok(self)
+    }
 }
 
 impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
@@ -569,15 +1044,134 @@ impl<UART: Uart> embedded_hal::serial::ErrorType for Rx<UART> {
 }
 
 impl<UART: Uart> Rx<UART> {
-    /// Get count of bytes in the receive FIFO
+    /// Get count of bytes in the RX ring buffer, saturated to `u8::MAX`
+    ///
+    /// Use [`Rx::buffered_len`] with RX ring buffers larger than 255 bytes.
     pub fn count(&self) -> Result<u8, EspError> {
+        Ok(core::cmp::min(self.buffered_len()?, u8::MAX as usize) as u8)
+    }
+
+    /// Get count of bytes in the RX ring buffer
+    pub fn buffered_len(&self) -> Result<usize, EspError> {
         let mut size = 0_u32;
         esp_result!(
             unsafe { uart_get_buffered_data_len(UART::port(), &mut size) },
-            size as u8
+            size as usize
         )
     }
 
+    /// Read received bytes into `buf`, waiting up to `timeout` (`None` waits forever)
+    /// for at least one byte to arrive
+    ///
+    /// Returns as soon as some data is available, with the number of bytes read,
+    /// which may be less than `buf.len()`. Returns `0` if the timeout expired.
+    pub fn read_bytes(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<usize, EspError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let available = self.buffered_len()?;
+
+        let len = if available > 0 {
+            self.read_raw(buf, available.min(buf.len()), 0)?
+        } else {
+            let len = self.read_raw(buf, 1, TickType::from(timeout).0)?;
+
+            if len > 0 && buf.len() > 1 {
+                let available = self.buffered_len()?.min(buf.len() - 1);
+
+                len + self.read_raw(&mut buf[1..], available, 0)?
+            } else {
+                len
+            }
+        };
+
+        Ok(len)
+    }
+
+    /// Asynchronously read received bytes into `buf`, completing as soon as some data is available
+    ///
+    /// Requires the driver to be created with [`Serial::new_async`].
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> Result<usize, EspError> {
+        loop {
+            let state = AsyncState::get(UART::port())?;
+
+            state.rx.reset();
+
+            let len = self.read_bytes(buf, Some(Duration::from_millis(0)))?;
+
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+
+            state.rx.wait().await;
+        }
+    }
+
+    fn read_raw(
+        &mut self,
+        buf: &mut [u8],
+        len: usize,
+        ticks: TickType_t,
+    ) -> Result<usize, EspError> {
+        if len == 0 {
+            return Ok(0);
+        }
+
+        // uart_read_bytes() returns error (-1) or how many bytes were read out
+        let len =
+            unsafe { uart_read_bytes(UART::port(), buf.as_mut_ptr() as *mut _, len as _, ticks) };
+
+        if len >= 0 {
+            Ok(len as usize)
+        } else {
+            Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap())
+        }
+    }
+
+    /// Waits for the next driver event for up to `timeout` (`None` waits forever)
+    ///
+    /// Returns `Ok(None)` if no event arrived in time.
+    /// Requires a non-zero `event_queue_size` in the configuration.
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, EspError> {
+        if self.event_queue.is_null() {
+            return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+        }
+
+        let mut event: uart_event_t = Default::default();
+
+        let received = unsafe {
+            xQueueReceive(
+                self.event_queue,
+                &mut event as *mut _ as *mut _,
+                TickType::from(timeout).0,
+            )
+        } != 0;
+
+        Ok(if received { Some(event.into()) } else { None })
+    }
+
+    /// Discard all data in the RX ring buffer and the hardware RX FIFO
+    pub fn clear(&mut self) -> Result<(), EspError> {
+        esp!(unsafe { uart_flush_input(UART::port()) })
+    }
+
+    /// Pops the position of the oldest detected RX pattern, relative to the
+    /// start of the data currently buffered in the driver
+    pub fn pattern_position(&mut self) -> Option<usize> {
+        let pos = unsafe { uart_pattern_pop_pos(UART::port()) };
+
+        if pos >= 0 {
+            Some(pos as usize)
+        } else {
+            None
+        }
+    }
+
     // /// Check if the receivers is idle
     // pub fn is_idle(&self) -> bool {
     //     unsafe { (*UART::ptr()).status.read().st_urx_out().is_rx_idle() }
@@ -618,6 +1212,255 @@ impl<UART: Uart> embedded_hal::serial::nb::Read<u8> for Rx<UART> {
     }
 }
 
+impl<UART: Uart> Tx<UART> {
+    /// Write `bytes` to the TX ring buffer (or directly to the hardware FIFO,
+    /// if the TX ring buffer is disabled), blocking until all of them are queued
+    ///
+    /// Returns the number of bytes written.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, EspError> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+
+        // `uart_write_bytes()` returns error (-1) or how many bytes were written
+        let len =
+            unsafe { uart_write_bytes(UART::port(), bytes.as_ptr() as *const _, bytes.len() as _) };
+
+        if len >= 0 {
+            Ok(len as usize)
+        } else {
+            Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap())
+        }
+    }
+
+    /// Write `bytes` like [`Tx::write_bytes`], followed by a break signal of `break_len` bit times
+    ///
+    /// Useful for protocols which need a break to separate frames, such as LIN or DMX512.
+    /// Requires a non-zero TX ring buffer size in the configuration.
+    pub fn write_with_break(&mut self, bytes: &[u8], break_len: u8) -> Result<usize, EspError> {
+        // `uart_write_bytes_with_break()` returns error (-1) or how many bytes were written
+        let len = unsafe {
+            uart_write_bytes_with_break(
+                UART::port(),
+                bytes.as_ptr() as *const _,
+                bytes.len() as _,
+                break_len as _,
+            )
+        };
+
+        if len >= 0 {
+            Ok(len as usize)
+        } else {
+            Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap())
+        }
+    }
+
+    /// Wait for up to `timeout` (`None` waits forever) until all queued data has been sent
+    pub fn flush_tx(&mut self, timeout: Option<Duration>) -> Result<(), EspError> {
+        esp!(unsafe { uart_wait_tx_done(UART::port(), TickType::from(timeout).0) })
+    }
+
+    /// Asynchronously write `bytes` directly to the hardware TX FIFO, completing as soon
+    /// as some of them have been written
+    ///
+    /// Returns the number of bytes written.
+    /// Requires the driver to be created with [`Serial::new_async`].
+    pub async fn write_async(&mut self, bytes: &[u8]) -> Result<usize, EspError> {
+        loop {
+            let state = AsyncState::get(UART::port())?;
+
+            if bytes.is_empty() {
+                return Ok(0);
+            }
+
+            state.tx.reset();
+
+            let len = unsafe {
+                uart_tx_chars(UART::port(), bytes.as_ptr() as *const _, bytes.len() as _)
+            };
+
+            if len < 0 {
+                return Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap());
+            } else if len > 0 {
+                return Ok(len as usize);
+            }
+
+            state
+                .wait_sent(UART::port(), ASYNC_WRITE_WAIT_CHARS)
+                .await?;
+        }
+    }
+
+    /// Asynchronously wait until all queued data has been sent
+    ///
+    /// Requires the driver to be created with [`Serial::new_async`].
+    pub async fn flush_async(&mut self) -> Result<(), EspError> {
+        loop {
+            let state = AsyncState::get(UART::port())?;
+
+            state.tx.reset();
+
+            match unsafe { uart_wait_tx_done(UART::port(), 0) } {
+                ESP_OK => return Ok(()),
+                ESP_ERR_TIMEOUT => {
+                    state
+                        .wait_sent(UART::port(), ASYNC_FLUSH_WAIT_CHARS)
+                        .await?
+                }
+                err => esp!(err)?,
+            }
+        }
+    }
+}
+
+/// Per-port state of the drivers created with [`Serial::new_async`]
+struct AsyncState {
+    enabled: AtomicBool,
+    tx_timer: AtomicPtr<c_types::c_void>,
+    rx: HalIsrNotification,
+    tx: HalIsrNotification,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ASYNC_STATE: AsyncState = AsyncState::new();
+
+static ASYNC_STATES: [AsyncState; SOC_UART_NUM as usize] = [ASYNC_STATE; SOC_UART_NUM as usize];
+
+impl AsyncState {
+    const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            tx_timer: AtomicPtr::new(ptr::null_mut()),
+            rx: HalIsrNotification::new(),
+            tx: HalIsrNotification::new(),
+        }
+    }
+
+    fn get(port: uart_port_t) -> Result<&'static Self, EspError> {
+        let state = &ASYNC_STATES[port as usize];
+
+        if state.enabled.load(Ordering::SeqCst) {
+            Ok(state)
+        } else {
+            Err(EspError::from(ESP_ERR_INVALID_STATE as i32).unwrap())
+        }
+    }
+
+    fn start(port: uart_port_t) -> Result<(), EspError> {
+        let state = &ASYNC_STATES[port as usize];
+
+        state.rx.reset();
+        state.tx.reset();
+
+        let tx_timer = esp_timer::create(
+            Self::handle_tx_timer,
+            port as usize as *mut _,
+            esp_timer_dispatch_t_ESP_TIMER_TASK,
+        )?;
+
+        state.tx_timer.store(tx_timer as _, Ordering::SeqCst);
+        state.enabled.store(true, Ordering::SeqCst);
+
+        unsafe { uart_set_select_notif_callback(port, Some(Self::handle_notif)) };
+
+        Ok(())
+    }
+
+    fn stop(port: uart_port_t) -> Result<(), EspError> {
+        let state = &ASYNC_STATES[port as usize];
+
+        if state.enabled.swap(false, Ordering::SeqCst) {
+            unsafe { uart_set_select_notif_callback(port, None) };
+
+            // Pending reads and writes fail with `ESP_ERR_INVALID_STATE` once woken up
+            state.rx.notify();
+            state.tx.notify();
+
+            let tx_timer = state.tx_timer.swap(ptr::null_mut(), Ordering::SeqCst) as _;
+
+            let _ = esp_timer::stop(tx_timer);
+            esp!(unsafe { esp_timer_delete(tx_timer) })?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits until `chars` characters can have been sent, or the driver reports
+    /// space in the TX FIFO
+    async fn wait_sent(&self, port: uart_port_t, chars: usize) -> Result<(), EspError> {
+        let tx_timer = self.tx_timer.load(Ordering::SeqCst) as esp_timer_handle_t;
+
+        esp_timer::stop(tx_timer)?;
+        esp!(unsafe { esp_timer_start_once(tx_timer, Self::send_time_us(port, chars)?) })?;
+
+        self.tx.wait().await;
+
+        Ok(())
+    }
+
+    /// Time needed to send `chars` characters with the current line settings
+    #[allow(non_upper_case_globals)]
+    fn send_time_us(port: uart_port_t, chars: usize) -> Result<u64, EspError> {
+        let mut baudrate = 0_u32;
+        let mut data_bits: uart_word_length_t = 0;
+        let mut parity: uart_parity_t = 0;
+        let mut stop_bits: uart_stop_bits_t = 0;
+
+        esp!(unsafe { uart_get_baudrate(port, &mut baudrate) })?;
+        esp!(unsafe { uart_get_word_length(port, &mut data_bits) })?;
+        esp!(unsafe { uart_get_parity(port, &mut parity) })?;
+        esp!(unsafe { uart_get_stop_bits(port, &mut stop_bits) })?;
+
+        let data_bits = match config::DataBits::from(data_bits) {
+            config::DataBits::DataBits5 => 5,
+            config::DataBits::DataBits6 => 6,
+            config::DataBits::DataBits7 => 7,
+            config::DataBits::DataBits8 => 8,
+        };
+
+        let parity_bits = match config::Parity::from(parity) {
+            config::Parity::ParityNone => 0,
+            _ => 1,
+        };
+
+        // 1.5 stop bits are rounded up
+        let stop_bits = match config::StopBits::from(stop_bits) {
+            config::StopBits::STOP1 => 1,
+            _ => 2,
+        };
+
+        // Plus the start bit
+        let bits = (1 + data_bits + parity_bits + stop_bits) * chars as u64;
+
+        Ok(bits * 1_000_000 / core::cmp::max(baudrate, 1) as u64)
+    }
+
+    /// Called by the driver from the UART ISR
+    #[allow(non_upper_case_globals)]
+    unsafe extern "C" fn handle_notif(
+        port: uart_port_t,
+        notif: uart_select_notif_t,
+        _task_woken: *mut BaseType_t,
+    ) {
+        let state = &ASYNC_STATES[port as usize];
+
+        match notif {
+            uart_select_notif_t_UART_SELECT_READ_NOTIF
+            | uart_select_notif_t_UART_SELECT_ERROR_NOTIF => {
+                state.rx.notify();
+            }
+            uart_select_notif_t_UART_SELECT_WRITE_NOTIF => {
+                state.tx.notify();
+            }
+            _ => (),
+        }
+    }
+
+    unsafe extern "C" fn handle_tx_timer(arg: *mut c_types::c_void) {
+        ASYNC_STATES[arg as usize].tx.notify();
+    }
+}
+
 // impl<UART: Uart> Tx<UART> {
 //     /// Get count of bytes in the transmitter FIFO
 //     pub fn count(&self) -> u8 {
@@ -680,16 +1523,172 @@ impl<UART: Uart> embedded_hal::serial::nb::Write<u8> for Tx<UART> {
     }
 }
 
-impl<UART: Uart> core::fmt::Write for Tx<UART>
-where
-    Tx<UART>: embedded_hal::serial::nb::Write<u8>,
-{
+impl<UART: Uart> core::fmt::Write for Tx<UART> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        use embedded_hal::serial::nb::Write;
-        s.as_bytes()
-            .iter()
-            .try_for_each(|c| nb::block!(self.write(*c)))
-            .map_err(|_| core::fmt::Error)
+        let mut bytes = s.as_bytes();
+
+        while !bytes.is_empty() {
+            match self.write_bytes(bytes) {
+                Ok(len) if len > 0 => bytes = &bytes[len..],
+                _ => return Err(core::fmt::Error),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Error for SerialError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart> embedded_io::ErrorType for Rx<UART> {
+    type Error = SerialError;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart> embedded_io::Read for Rx<UART> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read_bytes(buf, None).map_err(SerialError::other)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart> embedded_io::ReadReady for Rx<UART> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.buffered_len().map_err(SerialError::other)? > 0)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart> embedded_io::ErrorType for Tx<UART> {
+    type Error = SerialError;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart> embedded_io::Write for Tx<UART> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_bytes(buf).map_err(SerialError::other)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_tx(None).map_err(SerialError::other)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> embedded_io::ErrorType
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    type Error = SerialError;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> embedded_io::Read
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io::Read::read(&mut self.rx, buf)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> embedded_io::Write
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(&mut self.tx, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io::Write::flush(&mut self.tx)
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<UART: Uart> embedded_io_async::Read for Rx<UART> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read_async(buf).await.map_err(SerialError::other)
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<UART: Uart> embedded_io_async::Write for Tx<UART> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_async(buf).await.map_err(SerialError::other)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_async().await.map_err(SerialError::other)
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> embedded_io_async::Read
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Read::read(&mut self.rx, buf).await
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin>
+    embedded_io_async::Write for Serial<UART, TX, RX, CTS, RTS>
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Write::write(&mut self.tx, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io_async::Write::flush(&mut self.tx).await
+    }
+}
+
+#[cfg(feature = "std")]
+impl<UART: Uart> std::io::Read for Rx<UART> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read_bytes(buf, None)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<UART: Uart> std::io::Write for Tx<UART> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_bytes(buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush_tx(None)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> std::io::Read
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::io::Read::read(&mut self.rx, buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<UART: Uart, TX: OutputPin, RX: InputPin, CTS: InputPin, RTS: OutputPin> std::io::Write
+    for Serial<UART, TX, RX, CTS, RTS>
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut self.tx, buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::Write::flush(&mut self.tx)
     }
 }
 
@@ -717,5 +1716,14 @@ macro_rules! impl_uart {
 impl_uart!(UART0: 0);
 impl_uart!(UART1: 1);
 
-#[cfg(esp32)]
+#[cfg(any(esp32, esp32s3))]
 impl_uart!(UART2: 2);
+
+#[cfg(any(esp32, esp32s3))]
+const UART_COUNT: u32 = 3;
+
+#[cfg(not(any(esp32, esp32s3)))]
+const UART_COUNT: u32 = 2;
+
+// The UART instances above should match the SOC capabilities of the chip
+const _: () = assert!(UART_COUNT == SOC_UART_NUM);
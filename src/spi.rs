@@ -62,6 +62,7 @@ pub struct Pins<
 
 /// SPI configuration
 pub mod config {
+    use crate::interrupt::InterruptFlags;
     use crate::units::*;
 
     pub struct V02Type<T>(pub T);
@@ -102,6 +103,7 @@ pub mod config {
     pub struct Config {
         pub baudrate: Hertz,
         pub data_mode: embedded_hal::spi::Mode,
+        pub intr_flags: InterruptFlags,
     }
 
     impl Config {
@@ -120,6 +122,13 @@ pub mod config {
             self.data_mode = data_mode;
             self
         }
+
+        /// Flags for the allocation of the driver interrupt
+        #[must_use]
+        pub fn intr_flags(mut self, flags: InterruptFlags) -> Self {
+            self.intr_flags = flags;
+            self
+        }
     }
 
     impl Default for Config {
@@ -127,6 +136,7 @@ pub mod config {
             Self {
                 baudrate: Hertz(1_000_000),
                 data_mode: embedded_hal::spi::MODE_0,
+                intr_flags: Default::default(),
             }
         }
     }
@@ -359,6 +369,8 @@ impl<SPI: Spi, SCLK: OutputPin, SDO: OutputPin, SDI: InputPin + OutputPin, CS: O
         pins: Pins<SCLK, SDO, SDI, CS>,
         config: config::Config,
     ) -> Result<Self, EspError> {
+        config.intr_flags.validate()?;
+
         #[cfg(any(esp_idf_version = "4.4", esp_idf_version_major = "5"))]
         let bus_config = spi_bus_config_t {
             flags: SPICOMMON_BUSFLAG_MASTER,
@@ -385,6 +397,7 @@ impl<SPI: Spi, SCLK: OutputPin, SDO: OutputPin, SDI: InputPin + OutputPin, CS: O
                 //data3_io_num: -1,
             },
             //max_transfer_sz: SPI_MAX_TRANSFER_SIZE,
+            intr_flags: config.intr_flags.into(),
             ..Default::default()
         };
 
@@ -399,6 +412,7 @@ impl<SPI: Spi, SCLK: OutputPin, SDO: OutputPin, SDI: InputPin + OutputPin, CS: O
             quadhd_io_num: -1,
 
             //max_transfer_sz: SPI_MAX_TRANSFER_SIZE,
+            intr_flags: config.intr_flags.into(),
             ..Default::default()
         };
 
@@ -0,0 +1,205 @@
+//! Temperature sensors.
+//!
+//! [`TemperatureSensor`] abstracts over the sources of temperatures, so that code such as
+//! thermal throttling works with the internal sensor of the ESP32-S2, ESP32-S3 and ESP32-C3
+//! as well as with external sensors on chips which do not have one.
+//!
+//! # Example
+//!
+//! ```
+//! use esp_idf_hal::temp_sensor::*;
+//! use esp_idf_hal::prelude::*;
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//!
+//! let config = config::Config::new().range(config::Range::Celsius20To100);
+//! let mut sensor = PoweredTempSensor::new(peripherals.temp_sensor, &config)?;
+//!
+//! info!("Chip temperature: {}", sensor.read_celsius()?);
+//! ```
+
+use crate::units::Celsius;
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+use core::marker::PhantomData;
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+use esp_idf_sys::*;
+
+/// A source of temperature measurements
+pub trait TemperatureSensor {
+    type Error;
+
+    fn read_celsius(&mut self) -> Result<Celsius, Self::Error>;
+}
+
+impl<T: TemperatureSensor> TemperatureSensor for &mut T {
+    type Error = T::Error;
+
+    fn read_celsius(&mut self) -> Result<Celsius, Self::Error> {
+        (**self).read_celsius()
+    }
+}
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+pub mod config {
+    use esp_idf_sys::*;
+
+    /// Measurement range of the internal sensor
+    ///
+    /// Each range corresponds to a different DAC offset of the sensor. The narrower
+    /// the range around the expected temperature, the smaller the measurement error.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum Range {
+        /// 50°C to 125°C, error below 3°C
+        Celsius50To125,
+        /// 20°C to 100°C, error below 2°C
+        Celsius20To100,
+        /// -10°C to 80°C, error below 1°C
+        CelsiusMinus10To80,
+        /// -30°C to 50°C, error below 2°C
+        CelsiusMinus30To50,
+        /// -40°C to 20°C, error below 3°C
+        CelsiusMinus40To20,
+    }
+
+    impl Default for Range {
+        fn default() -> Self {
+            Self::CelsiusMinus10To80
+        }
+    }
+
+    impl From<Range> for temp_sensor_dac_offset_t {
+        fn from(range: Range) -> Self {
+            match range {
+                Range::Celsius50To125 => temp_sensor_dac_offset_t_TSENS_DAC_L0,
+                Range::Celsius20To100 => temp_sensor_dac_offset_t_TSENS_DAC_L1,
+                Range::CelsiusMinus10To80 => temp_sensor_dac_offset_t_TSENS_DAC_L2,
+                Range::CelsiusMinus30To50 => temp_sensor_dac_offset_t_TSENS_DAC_L3,
+                Range::CelsiusMinus40To20 => temp_sensor_dac_offset_t_TSENS_DAC_L4,
+            }
+        }
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    pub struct Config {
+        pub range: Range,
+        /// Divider of the sensor clock
+        pub clk_div: u8,
+    }
+
+    impl Config {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn range(mut self, range: Range) -> Self {
+            self.range = range;
+            self
+        }
+
+        #[must_use]
+        pub fn clk_div(mut self, clk_div: u8) -> Self {
+            self.clk_div = clk_div;
+            self
+        }
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                range: Default::default(),
+                clk_div: 6,
+            }
+        }
+    }
+
+    impl From<&Config> for temp_sensor_config_t {
+        fn from(config: &Config) -> Self {
+            temp_sensor_config_t {
+                dac_offset: config.range.into(),
+                clk_div: config.clk_div,
+            }
+        }
+    }
+}
+
+/// The internal temperature sensor
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+pub struct TempSensor(PhantomData<*const ()>);
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+impl TempSensor {
+    /// # Safety
+    ///
+    /// Care should be taken not to instnatiate this temperature sensor instance, if it is already instantiated and used elsewhere
+    pub unsafe fn new() -> Self {
+        TempSensor(PhantomData)
+    }
+}
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+unsafe impl Send for TempSensor {}
+
+/// Driver of the internal temperature sensor
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+pub struct PoweredTempSensor {
+    sensor: TempSensor,
+}
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+impl PoweredTempSensor {
+    pub fn new(sensor: TempSensor, config: &config::Config) -> Result<Self, EspError> {
+        esp!(unsafe { temp_sensor_set_config(config.into()) })?;
+        esp!(unsafe { temp_sensor_start() })?;
+
+        Ok(Self { sensor })
+    }
+
+    /// Change the measurement range
+    pub fn set_range(&mut self, range: config::Range) -> Result<(), EspError> {
+        let mut config = temp_sensor_config_t::default();
+
+        esp!(unsafe { temp_sensor_get_config(&mut config) })?;
+
+        config.dac_offset = range.into();
+
+        // The configuration only takes effect when the sensor is started
+        esp!(unsafe { temp_sensor_stop() })?;
+        esp!(unsafe { temp_sensor_set_config(config) })?;
+        esp!(unsafe { temp_sensor_start() })
+    }
+
+    /// Read the raw output of the sensor, which depends on the measurement range
+    pub fn read_raw(&mut self) -> Result<u32, EspError> {
+        let mut raw = 0;
+
+        esp!(unsafe { temp_sensor_read_raw(&mut raw) })?;
+
+        Ok(raw)
+    }
+
+    pub fn read_celsius(&mut self) -> Result<Celsius, EspError> {
+        let mut celsius = 0.0;
+
+        esp!(unsafe { temp_sensor_read_celsius(&mut celsius) })?;
+
+        Ok(Celsius(celsius))
+    }
+
+    pub fn release(self) -> Result<TempSensor, EspError> {
+        esp!(unsafe { temp_sensor_stop() })?;
+
+        Ok(self.sensor)
+    }
+}
+
+#[cfg(any(esp32s2, esp32s3, esp32c3))]
+impl TemperatureSensor for PoweredTempSensor {
+    type Error = EspError;
+
+    fn read_celsius(&mut self) -> Result<Celsius, Self::Error> {
+        PoweredTempSensor::read_celsius(self)
+    }
+}
@@ -0,0 +1,479 @@
+//! General purpose timer driver.
+//!
+//! The 64-bit timers of the timer groups count up or down at the APB clock
+//! divided by a configurable divider, and raise an interrupt when the counter reaches
+//! the alarm value, optionally reloading it for periodic alarms.
+//!
+//! # Example
+//!
+//! ```
+//! use esp_idf_hal::prelude::*;
+//! use esp_idf_hal::timer::*;
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//!
+//! let config = config::Config::new().resolution(Hertz(1_000_000)).auto_reload(true);
+//! let mut timer = TimerDriver::new(peripherals.timer00, &config)?;
+//!
+//! // Sample every 500us
+//! timer.set_alarm(500)?;
+//! timer.enable_alarm(true)?;
+//! timer.enable(true)?;
+//!
+//! loop {
+//!     timer.wait().await?;
+//!     sample();
+//! }
+//! ```
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "alloc")]
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem;
+
+use esp_idf_sys::*;
+
+use crate::interrupt;
+use crate::interrupt::asynch::HalIsrNotification;
+#[cfg(feature = "alloc")]
+use crate::interrupt::CriticalSection;
+
+/// Frequency of the clock counted by the timers, before the divider
+pub const SOURCE_CLOCK_HZ: u32 = 80_000_000;
+
+pub mod config {
+    use esp_idf_sys::*;
+
+    use crate::units::*;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum Direction {
+        Up,
+        Down,
+    }
+
+    impl From<Direction> for timer_count_dir_t {
+        fn from(direction: Direction) -> Self {
+            match direction {
+                Direction::Up => timer_count_dir_t_TIMER_COUNT_UP,
+                Direction::Down => timer_count_dir_t_TIMER_COUNT_DOWN,
+            }
+        }
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    pub struct Config {
+        /// Divider of the source clock, from 2 to 65536
+        pub divider: u32,
+        pub direction: Direction,
+        /// Whether the counter is reloaded with its initial value when an alarm is raised
+        pub auto_reload: bool,
+    }
+
+    impl Config {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        #[must_use]
+        pub fn divider(mut self, divider: u32) -> Self {
+            self.divider = divider;
+            self
+        }
+
+        /// Set the divider so that the counter ticks at `resolution`, or slightly faster
+        /// if the source clock is not a multiple of it
+        ///
+        /// The divider is not checked here: [`super::TimerDriver::new`] fails with
+        /// `ESP_ERR_INVALID_ARG` for resolutions above `SOURCE_CLOCK_HZ / 2`
+        /// or below `SOURCE_CLOCK_HZ / 65536`.
+        #[must_use]
+        pub fn resolution(mut self, resolution: Hertz) -> Self {
+            self.divider = super::SOURCE_CLOCK_HZ / core::cmp::max(resolution.0, 1);
+            self
+        }
+
+        #[must_use]
+        pub fn direction(mut self, direction: Direction) -> Self {
+            self.direction = direction;
+            self
+        }
+
+        #[must_use]
+        pub fn auto_reload(mut self, auto_reload: bool) -> Self {
+            self.auto_reload = auto_reload;
+            self
+        }
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                divider: 80,
+                direction: Direction::Up,
+                auto_reload: false,
+            }
+        }
+    }
+}
+
+pub trait Timer: Send {
+    fn group() -> timer_group_t;
+
+    fn index() -> timer_idx_t;
+}
+
+/// General purpose timer driver
+pub struct TimerDriver<TIMER: Timer> {
+    timer: TIMER,
+    divider: u32,
+    direction: config::Direction,
+}
+
+impl<TIMER: Timer> TimerDriver<TIMER> {
+    /// Initialize the timer, paused at a counter value of 0 and with the alarm disabled
+    pub fn new(timer: TIMER, config: &config::Config) -> Result<Self, EspError> {
+        if !(2..=65536).contains(&config.divider) {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        let timer_config = timer_config_t {
+            alarm_en: timer_alarm_t_TIMER_ALARM_DIS,
+            counter_en: timer_start_t_TIMER_PAUSE,
+            intr_type: timer_intr_mode_t_TIMER_INTR_LEVEL,
+            counter_dir: config.direction.into(),
+            auto_reload: if config.auto_reload {
+                timer_autoreload_t_TIMER_AUTORELOAD_EN
+            } else {
+                timer_autoreload_t_TIMER_AUTORELOAD_DIS
+            },
+            divider: config.divider,
+            ..Default::default()
+        };
+
+        esp!(unsafe { timer_init(TIMER::group(), TIMER::index(), &timer_config) })?;
+
+        let state = Self::state();
+
+        state.notification.reset();
+
+        if let Err(e) = esp!(unsafe {
+            timer_isr_callback_add(
+                TIMER::group(),
+                TIMER::index(),
+                Some(handle_isr),
+                state as *const _ as *mut _,
+                0,
+            )
+        }) {
+            unsafe { timer_deinit(TIMER::group(), TIMER::index()) };
+
+            return Err(e);
+        }
+
+        Ok(Self {
+            timer,
+            divider: config.divider,
+            direction: config.direction,
+        })
+    }
+
+    /// Frequency at which the counter ticks
+    pub fn tick_hz(&self) -> u64 {
+        (SOURCE_CLOCK_HZ / self.divider) as u64
+    }
+
+    /// Start (`true`) or pause (`false`) the counter
+    pub fn enable(&mut self, enable: bool) -> Result<(), EspError> {
+        if enable {
+            esp!(unsafe { timer_start(TIMER::group(), TIMER::index()) })
+        } else {
+            esp!(unsafe { timer_pause(TIMER::group(), TIMER::index()) })
+        }
+    }
+
+    pub fn counter(&self) -> Result<u64, EspError> {
+        let mut value = 0_u64;
+
+        esp!(unsafe { timer_get_counter_value(TIMER::group(), TIMER::index(), &mut value) })?;
+
+        Ok(value)
+    }
+
+    /// Set the counter; it is also the value the counter is reloaded with on alarms
+    pub fn set_counter(&mut self, value: u64) -> Result<(), EspError> {
+        esp!(unsafe { timer_set_counter_value(TIMER::group(), TIMER::index(), value) })
+    }
+
+    /// Change the divider of the source clock; takes effect immediately
+    pub fn set_divider(&mut self, divider: u32) -> Result<(), EspError> {
+        if !(2..=65536).contains(&divider) {
+            return Err(EspError::from(ESP_ERR_INVALID_ARG as i32).unwrap());
+        }
+
+        esp!(unsafe { timer_set_divider(TIMER::group(), TIMER::index(), divider) })?;
+
+        self.divider = divider;
+
+        Ok(())
+    }
+
+    pub fn set_direction(&mut self, direction: config::Direction) -> Result<(), EspError> {
+        esp!(unsafe { timer_set_counter_mode(TIMER::group(), TIMER::index(), direction.into()) })?;
+
+        self.direction = direction;
+
+        Ok(())
+    }
+
+    pub fn alarm(&self) -> Result<u64, EspError> {
+        let mut value = 0_u64;
+
+        esp!(unsafe { timer_get_alarm_value(TIMER::group(), TIMER::index(), &mut value) })?;
+
+        Ok(value)
+    }
+
+    /// Set the counter value at which the alarm is raised
+    pub fn set_alarm(&mut self, value: u64) -> Result<(), EspError> {
+        esp!(unsafe { timer_set_alarm_value(TIMER::group(), TIMER::index(), value) })
+    }
+
+    /// Enable or disable the alarm
+    ///
+    /// Unless auto-reload is enabled, the hardware disables the alarm once it is raised.
+    pub fn enable_alarm(&mut self, enable: bool) -> Result<(), EspError> {
+        esp!(unsafe {
+            timer_set_alarm(
+                TIMER::group(),
+                TIMER::index(),
+                if enable {
+                    timer_alarm_t_TIMER_ALARM_EN
+                } else {
+                    timer_alarm_t_TIMER_ALARM_DIS
+                },
+            )
+        })
+    }
+
+    pub fn set_auto_reload(&mut self, auto_reload: bool) -> Result<(), EspError> {
+        esp!(unsafe {
+            timer_set_auto_reload(
+                TIMER::group(),
+                TIMER::index(),
+                if auto_reload {
+                    timer_autoreload_t_TIMER_AUTORELOAD_EN
+                } else {
+                    timer_autoreload_t_TIMER_AUTORELOAD_DIS
+                },
+            )
+        })
+    }
+
+    /// Call `callback` from the timer ISR whenever the alarm is raised
+    ///
+    /// # Safety
+    ///
+    /// The callback runs in an ISR context and within a critical section, so it must
+    /// not block, allocate or otherwise call functions which are not allowed in an ISR.
+    /// Functions further down the callback which yield (e.g. by notifying a task)
+    /// make the ISR yield once the callback returns.
+    #[cfg(feature = "alloc")]
+    pub unsafe fn subscribe(
+        &mut self,
+        callback: impl FnMut() + Send + 'static,
+    ) -> Result<(), EspError> {
+        Self::state().replace_callback(Some(Box::new(callback)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn unsubscribe(&mut self) -> Result<(), EspError> {
+        Self::state().replace_callback(None);
+
+        Ok(())
+    }
+
+    /// Wait asynchronously for the alarm to be raised
+    ///
+    /// Only alarms raised after the call are waited for.
+    pub async fn wait(&mut self) -> Result<(), EspError> {
+        let notification = &Self::state().notification;
+
+        notification.reset();
+        notification.wait().await;
+
+        Ok(())
+    }
+
+    /// Wait asynchronously for `ticks` ticks of the counter
+    ///
+    /// Uses the alarm, which is disabled afterwards, and starts the counter if it was paused.
+    /// If the future is dropped before the alarm is raised, the alarm is disabled as well,
+    /// but the counter keeps running.
+    pub async fn delay(&mut self, ticks: u64) -> Result<(), EspError> {
+        let counter = self.counter()?;
+
+        let alarm = match self.direction {
+            config::Direction::Up => counter.wrapping_add(ticks),
+            config::Direction::Down => counter.wrapping_sub(ticks),
+        };
+
+        self.set_alarm(alarm)?;
+
+        let notification = &Self::state().notification;
+
+        notification.reset();
+
+        let guard = AlarmGuard::<TIMER>(PhantomData);
+
+        self.enable_alarm(true)?;
+        self.enable(true)?;
+
+        notification.wait().await;
+
+        mem::forget(guard);
+
+        self.enable_alarm(false)
+    }
+
+    pub fn release(self) -> Result<TIMER, EspError> {
+        esp!(unsafe { timer_pause(TIMER::group(), TIMER::index()) })?;
+        esp!(unsafe { timer_isr_callback_remove(TIMER::group(), TIMER::index()) })?;
+        esp!(unsafe { timer_deinit(TIMER::group(), TIMER::index()) })?;
+
+        #[cfg(feature = "alloc")]
+        Self::state().replace_callback(None);
+
+        Ok(self.timer)
+    }
+
+    fn state() -> &'static TimerState {
+        &TIMER_STATES[TIMER::group() as usize * 2 + TIMER::index() as usize]
+    }
+}
+
+/// Disables the alarm of a [`TimerDriver::delay`] which did not complete
+struct AlarmGuard<TIMER: Timer>(PhantomData<TIMER>);
+
+impl<TIMER: Timer> Drop for AlarmGuard<TIMER> {
+    fn drop(&mut self) {
+        // Best effort, as a delay can be cancelled at any point
+        let _ = unsafe {
+            timer_set_alarm(
+                TIMER::group(),
+                TIMER::index(),
+                timer_alarm_t_TIMER_ALARM_DIS,
+            )
+        };
+    }
+}
+
+/// State shared with the ISR of one timer
+struct TimerState {
+    notification: HalIsrNotification,
+    /// Guards `callback` against the ISR, which may run on the other core
+    #[cfg(feature = "alloc")]
+    cs: CriticalSection,
+    #[cfg(feature = "alloc")]
+    callback: UnsafeCell<Option<Box<dyn FnMut() + Send>>>,
+}
+
+unsafe impl Sync for TimerState {}
+
+impl TimerState {
+    const fn new() -> Self {
+        Self {
+            notification: HalIsrNotification::new(),
+            #[cfg(feature = "alloc")]
+            cs: CriticalSection::new(),
+            #[cfg(feature = "alloc")]
+            callback: UnsafeCell::new(None),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn replace_callback(&self, callback: Option<Box<dyn FnMut() + Send>>) {
+        let previous = {
+            let _guard = self.cs.enter();
+
+            mem::replace(unsafe { &mut *self.callback.get() }, callback)
+        };
+
+        // Dropped outside of the critical section
+        drop(previous);
+    }
+}
+
+static TIMER_STATES: [TimerState; 4] = [
+    TimerState::new(),
+    TimerState::new(),
+    TimerState::new(),
+    TimerState::new(),
+];
+
+/// Notifies the async waiters and calls the subscribed callback
+///
+/// Yields requested meanwhile are deferred until the ISR returns, by telling
+/// the timer driver that a higher priority task was woken.
+extern "C" fn handle_isr(arg: *mut c_types::c_void) -> bool {
+    let state = unsafe { &*(arg as *const TimerState) };
+
+    unsafe {
+        interrupt::with_deferred_isr_yield(|| {
+            state.notification.notify();
+
+            #[cfg(feature = "alloc")]
+            {
+                let _guard = state.cs.enter();
+
+                if let Some(callback) = (*state.callback.get()).as_mut() {
+                    callback();
+                }
+            }
+        })
+    }
+}
+
+macro_rules! impl_timer {
+    ($timer:ident: $group:expr, $index:expr) => {
+        pub struct $timer(PhantomData<*const ()>);
+
+        impl $timer {
+            /// # Safety
+            ///
+            /// Care should be taken not to instnatiate this timer instance, if it is already instantiated and used elsewhere
+            pub unsafe fn new() -> Self {
+                $timer(PhantomData)
+            }
+        }
+
+        unsafe impl Send for $timer {}
+
+        impl Timer for $timer {
+            #[inline(always)]
+            fn group() -> timer_group_t {
+                $group
+            }
+
+            #[inline(always)]
+            fn index() -> timer_idx_t {
+                $index
+            }
+        }
+    };
+}
+
+impl_timer!(TIMER00: timer_group_t_TIMER_GROUP_0, timer_idx_t_TIMER_0);
+#[cfg(not(esp32c3))]
+impl_timer!(TIMER01: timer_group_t_TIMER_GROUP_0, timer_idx_t_TIMER_1);
+impl_timer!(TIMER10: timer_group_t_TIMER_GROUP_1, timer_idx_t_TIMER_0);
+#[cfg(not(esp32c3))]
+impl_timer!(TIMER11: timer_group_t_TIMER_GROUP_1, timer_idx_t_TIMER_1);
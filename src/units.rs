@@ -1,4 +1,4 @@
-//! Units of measurement implementation for times and frequencies.
+//! Units of measurement implementation for times, frequencies, voltages and temperatures.
 //!
 //! It provides type safety, easy conversion and limited arithmetic support.
 //!
@@ -24,10 +24,13 @@ pub trait Quantity: Sized {}
 pub trait Time: Quantity + Into<NanoSeconds> {}
 pub trait Frequency: Quantity + Into<Hertz> {}
 pub trait Count: Quantity + Into<Ticks> {}
+pub trait Voltage: Quantity + Into<MilliVolts> {}
+pub trait Temperature: Quantity + Into<Celsius> {}
 
 pub trait TimeU64: Quantity + Into<NanoSecondsU64> {}
 pub trait FrequencyU64: Quantity + Into<HertzU64> {}
 pub trait CountU64: Quantity + Into<TicksU64> {}
+pub trait VoltageU64: Quantity + Into<MilliVoltsU64> {}
 
 /// defines and implements extension traits for quantities with units
 macro_rules! define {
@@ -294,9 +297,57 @@ define_large!(
     (Time,      MicroSeconds, us,    TimeU64,      MicroSecondsU64, us_large,    "us"  ),
     (Time,      MilliSeconds, ms,    TimeU64,      MilliSecondsU64, ms_large,    "ms"  ),
     (Time,      Seconds,      s,     TimeU64,      SecondsU64,      s_large,     "s"   ),
-    (Count,     Ticks,        ticks, CountU64,     TicksU64,        ticks_large, ""    )
+    (Count,     Ticks,        ticks, CountU64,     TicksU64,        ticks_large, ""    ),
+    (Voltage,   MilliVolts,   mV,    VoltageU64,   MilliVoltsU64,   mV_large,    "mV"  )
 );
 
+/// Temperature in degrees Celsius
+///
+/// Unlike the other quantities, temperatures are signed and fractional.
+#[derive(PartialEq, PartialOrd, Clone, Copy, Default)]
+pub struct Celsius(pub f32);
+
+impl Quantity for Celsius {}
+impl Temperature for Celsius {}
+
+impl From<Celsius> for f32 {
+    fn from(x: Celsius) -> Self {
+        x.0
+    }
+}
+
+impl From<f32> for Celsius {
+    fn from(x: f32) -> Celsius {
+        Celsius(x)
+    }
+}
+
+impl fmt::Debug for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}°C", self.0)
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}°C", self.0)
+    }
+}
+
+impl core::ops::Add<Celsius> for Celsius {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl core::ops::Sub<Celsius> for Celsius {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
 #[rustfmt::skip::macros(convert)]
 convert!(
     (KiloHertz,    KiloHertzU64,    Hertz,        HertzU64,        1_000         ),